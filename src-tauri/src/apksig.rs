@@ -0,0 +1,1005 @@
+//! Verification of v1 (JAR), v2, v3 and v3.1 APK signatures.
+
+use std::{collections::HashMap, io::{Cursor, Read}};
+
+use base64::{engine::general_purpose, Engine};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Sign, Pss, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use zip::ZipArchive;
+
+use crate::{axml::{u16_at, u32_at}, cert::{self, children, read_tlv, Tlv}};
+
+const EOCD_MAGIC: u32 = 0x06054b50;
+const EOCD_SIZE: usize = 22;
+const SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+const V2_BLOCK_ID: u32 = 0x7109871a;
+const V3_BLOCK_ID: u32 = 0xf05368c0;
+const V31_BLOCK_ID: u32 = 0x1b93ad61;
+
+const STRIPPING_PROTECTION_ATTR_ID: u32 = 0xbeeff00d;
+const PROOF_OF_ROTATION_ATTR_ID: u32 = 0x3ba06f8c;
+const ROTATION_MIN_SDK_ATTR_ID: u32 = 0x559f8b02;
+
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+
+#[derive(Debug, serde::Serialize, Clone, PartialEq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub key_algorithm: String,
+    pub key_size: Option<usize>,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct Signer {
+    pub certificate: Option<CertificateInfo>,
+    pub min_sdk: Option<u32>,
+    pub max_sdk: Option<u32>,
+    pub algorithms: Vec<String>,
+    pub verified: bool,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct SchemeResult {
+    pub scheme: String,
+    pub verified: bool,
+    pub signers: Vec<Signer>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct LineageEntry {
+    pub certificate: CertificateInfo,
+    pub capabilities: Vec<String>,
+    pub verified: bool,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct SignatureReport {
+    pub verified: bool,
+    pub schemes: Vec<SchemeResult>,
+    pub lineage: Vec<LineageEntry>,
+    pub mismatches: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct SignatureSummary {
+    pub verified: bool,
+    pub schemes: Vec<String>,
+    pub signer: Option<CertificateInfo>,
+    pub rotated: bool,
+}
+
+impl SignatureReport {
+    pub fn summary(&self) -> SignatureSummary {
+        // The newest scheme carries the current signer, older ones may still use a rotated key
+        let signer = self.schemes.iter().rev()
+            .find_map(|scheme| scheme.signers.iter().filter_map(|signer| signer.certificate.clone()).next_back());
+        SignatureSummary {
+            verified: self.verified,
+            schemes: self.schemes.iter().filter(|scheme| scheme.verified).map(|scheme| scheme.scheme.clone()).collect(),
+            signer,
+            rotated: self.lineage.len() > 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "SHA-1",
+            HashAlgorithm::Sha256 => "SHA2-256",
+            HashAlgorithm::Sha512 => "SHA2-512",
+        }
+    }
+
+    fn from_oid(oid: &str) -> Option<Self> {
+        match oid {
+            "1.3.14.3.2.26" => Some(HashAlgorithm::Sha1),
+            "2.16.840.1.101.3.4.2.1" => Some(HashAlgorithm::Sha256),
+            "2.16.840.1.101.3.4.2.3" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn from_jar_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "SHA1" | "SHA-1" => Some(HashAlgorithm::Sha1),
+            "SHA-256" => Some(HashAlgorithm::Sha256),
+            "SHA-512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignatureScheme {
+    RsaPss,
+    RsaPkcs1,
+    Ecdsa,
+    Dsa,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ContentDigest {
+    ChunkedSha256,
+    ChunkedSha512,
+    Verity,
+}
+
+struct Algorithm {
+    id: u32,
+    name: &'static str,
+    scheme: SignatureScheme,
+    hash: HashAlgorithm,
+    content: ContentDigest,
+}
+
+const ALGORITHMS: &[Algorithm] = &[
+    Algorithm { id: 0x0101, name: "RSASSA-PSS with SHA2-256", scheme: SignatureScheme::RsaPss, hash: HashAlgorithm::Sha256, content: ContentDigest::ChunkedSha256 },
+    Algorithm { id: 0x0102, name: "RSASSA-PSS with SHA2-512", scheme: SignatureScheme::RsaPss, hash: HashAlgorithm::Sha512, content: ContentDigest::ChunkedSha512 },
+    Algorithm { id: 0x0103, name: "RSASSA-PKCS1-v1_5 with SHA2-256", scheme: SignatureScheme::RsaPkcs1, hash: HashAlgorithm::Sha256, content: ContentDigest::ChunkedSha256 },
+    Algorithm { id: 0x0104, name: "RSASSA-PKCS1-v1_5 with SHA2-512", scheme: SignatureScheme::RsaPkcs1, hash: HashAlgorithm::Sha512, content: ContentDigest::ChunkedSha512 },
+    Algorithm { id: 0x0201, name: "ECDSA with SHA2-256", scheme: SignatureScheme::Ecdsa, hash: HashAlgorithm::Sha256, content: ContentDigest::ChunkedSha256 },
+    Algorithm { id: 0x0202, name: "ECDSA with SHA2-512", scheme: SignatureScheme::Ecdsa, hash: HashAlgorithm::Sha512, content: ContentDigest::ChunkedSha512 },
+    Algorithm { id: 0x0301, name: "DSA with SHA2-256", scheme: SignatureScheme::Dsa, hash: HashAlgorithm::Sha256, content: ContentDigest::ChunkedSha256 },
+    Algorithm { id: 0x0421, name: "RSASSA-PKCS1-v1_5 with SHA2-256 (verity)", scheme: SignatureScheme::RsaPkcs1, hash: HashAlgorithm::Sha256, content: ContentDigest::Verity },
+    Algorithm { id: 0x0423, name: "ECDSA with SHA2-256 (verity)", scheme: SignatureScheme::Ecdsa, hash: HashAlgorithm::Sha256, content: ContentDigest::Verity },
+    Algorithm { id: 0x0425, name: "DSA with SHA2-256 (verity)", scheme: SignatureScheme::Dsa, hash: HashAlgorithm::Sha256, content: ContentDigest::Verity },
+];
+
+fn algorithm(id: u32) -> Option<&'static Algorithm> {
+    ALGORITHMS.iter().find(|algorithm| algorithm.id == id)
+}
+
+/// Little-endian reader over the length-prefixed structures of the APK Signing Block.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let value = u32_at(self.data, 0)?;
+        self.data = &self.data[4..];
+        Some(value)
+    }
+
+    fn prefixed(&mut self) -> Option<&'a [u8]> {
+        let length = self.u32()? as usize;
+        let value = self.data.get(..length)?;
+        self.data = &self.data[length..];
+        Some(value)
+    }
+
+    fn items(mut self) -> Option<Vec<&'a [u8]>> {
+        let mut items = Vec::new();
+        while !self.data.is_empty() {
+            items.push(self.prefixed()?);
+        }
+        Some(items)
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn oid_to_string(content: &[u8]) -> String {
+    let mut parts: Vec<u64> = Vec::new();
+    let mut value = 0u64;
+    for byte in content {
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first);
+                parts.push(value - first * 40);
+            } else {
+                parts.push(value);
+            }
+            value = 0;
+        }
+    }
+    parts.iter().map(|part| part.to_string()).collect::<Vec<_>>().join(".")
+}
+
+/// The OID at the start of an AlgorithmIdentifier or attribute.
+fn first_oid(tlv: &Tlv) -> Option<String> {
+    children(tlv.content).first().filter(|oid| oid.tag == 0x06).map(|oid| oid_to_string(oid.content))
+}
+
+fn string_value(tlv: &Tlv) -> String {
+    match tlv.tag {
+        // BMPString
+        0x1e => String::from_utf16_lossy(&tlv.content.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect::<Vec<_>>()),
+        _ => String::from_utf8_lossy(tlv.content).to_string(),
+    }
+}
+
+/// Formats an X.500 name the way apksigner and keytool print it, most specific RDN first.
+fn format_name(name: &Tlv) -> String {
+    let mut parts = Vec::new();
+    for rdn in children(name.content).iter().rev() {
+        for attribute in children(rdn.content) {
+            let fields = children(attribute.content);
+            let (Some(oid), Some(value)) = (fields.first(), fields.get(1)) else {
+                continue;
+            };
+            let oid = oid_to_string(oid.content);
+            let key = match oid.as_str() {
+                "2.5.4.3" => "CN",
+                "2.5.4.6" => "C",
+                "2.5.4.7" => "L",
+                "2.5.4.8" => "ST",
+                "2.5.4.10" => "O",
+                "2.5.4.11" => "OU",
+                "1.2.840.113549.1.9.1" => "EMAILADDRESS",
+                _ => oid.as_str(),
+            };
+            parts.push(format!("{}={}", key, string_value(value)));
+        }
+    }
+    parts.join(", ")
+}
+
+fn format_time(tlv: &Tlv) -> String {
+    let text = String::from_utf8_lossy(tlv.content);
+    let text = text.trim_end_matches('Z');
+    let (year, rest) = match tlv.tag {
+        // UTCTime uses two digit years, 50-99 are 19xx
+        0x17 if text.len() >= 2 => {
+            let year: u32 = text[..2].parse().unwrap_or_default();
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &text[2..])
+        }
+        0x18 if text.len() >= 4 => (text[..4].parse().unwrap_or_default(), &text[4..]),
+        _ => return text.to_string(),
+    };
+    let field = |index: usize| rest.get(index * 2..index * 2 + 2).unwrap_or("00");
+    format!("{}-{}-{} {}:{}:{} UTC", year, field(0), field(1), field(2), field(3), field(4))
+}
+
+fn key_details(spki: &[u8]) -> (String, Option<usize>) {
+    let Some((spki_tlv, _)) = read_tlv(spki) else {
+        return ("Unknown".to_string(), None);
+    };
+    let Some(identifier) = children(spki_tlv.content).first().copied() else {
+        return ("Unknown".to_string(), None);
+    };
+    let parameters = children(identifier.content).get(1).filter(|tlv| tlv.tag == 0x06).map(|tlv| oid_to_string(tlv.content));
+    match first_oid(&identifier).as_deref() {
+        Some("1.2.840.113549.1.1.1") => ("RSA".to_string(), RsaPublicKey::from_public_key_der(spki).ok().map(|key| rsa::traits::PublicKeyParts::size(&key) * 8)),
+        Some("1.2.840.10045.2.1") => ("EC".to_string(), match parameters.as_deref() {
+            Some("1.2.840.10045.3.1.7") => Some(256),
+            Some("1.3.132.0.34") => Some(384),
+            Some("1.3.132.0.35") => Some(521),
+            _ => None,
+        }),
+        Some("1.2.840.10040.4.1") => ("DSA".to_string(), None),
+        Some(oid) => (oid.to_string(), None),
+        None => ("Unknown".to_string(), None),
+    }
+}
+
+pub fn certificate_info(der: &[u8]) -> Option<CertificateInfo> {
+    // serialNumber, signature, issuer, validity, subject, subjectPublicKeyInfo
+    let fields = cert::tbs_fields(der)?;
+    let validity = children(fields.get(3)?.content);
+    let serial = fields.first()?.content;
+    let serial = &serial[serial.iter().position(|byte| *byte != 0).unwrap_or(serial.len().saturating_sub(1))..];
+    let (key_algorithm, key_size) = key_details(fields.get(5)?.raw);
+    Some(CertificateInfo {
+        subject: format_name(fields.get(4)?),
+        issuer: format_name(fields.get(2)?),
+        serial_number: hex(serial),
+        not_before: format_time(validity.first()?),
+        not_after: format_time(validity.get(1)?),
+        key_algorithm,
+        key_size,
+        sha1: hex(&Sha1::digest(der)),
+        sha256: hex(&Sha256::digest(der)),
+    })
+}
+
+fn verify_signature(spki: &[u8], scheme: SignatureScheme, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let digest = hash.digest(message);
+    let verified = match scheme {
+        SignatureScheme::RsaPss | SignatureScheme::RsaPkcs1 => {
+            let key = RsaPublicKey::from_public_key_der(spki).map_err(|e| e.to_string())?;
+            match (scheme, hash) {
+                (SignatureScheme::RsaPss, HashAlgorithm::Sha1) => key.verify(Pss::new::<Sha1>(), &digest, signature),
+                (SignatureScheme::RsaPss, HashAlgorithm::Sha256) => key.verify(Pss::new::<Sha256>(), &digest, signature),
+                (SignatureScheme::RsaPss, HashAlgorithm::Sha512) => key.verify(Pss::new::<Sha512>(), &digest, signature),
+                (_, HashAlgorithm::Sha1) => key.verify(Pkcs1v15Sign::new::<Sha1>(), &digest, signature),
+                (_, HashAlgorithm::Sha256) => key.verify(Pkcs1v15Sign::new::<Sha256>(), &digest, signature),
+                (_, HashAlgorithm::Sha512) => key.verify(Pkcs1v15Sign::new::<Sha512>(), &digest, signature),
+            }.is_ok()
+        }
+        SignatureScheme::Ecdsa => {
+            let (_, curve) = key_details(spki);
+            match curve {
+                Some(256) => {
+                    let key = p256::ecdsa::VerifyingKey::from_public_key_der(spki).map_err(|e| e.to_string())?;
+                    let signature = p256::ecdsa::Signature::from_der(signature).map_err(|e| e.to_string())?;
+                    key.verify_prehash(&digest, &signature).is_ok()
+                }
+                Some(384) => {
+                    let key = p384::ecdsa::VerifyingKey::from_public_key_der(spki).map_err(|e| e.to_string())?;
+                    let signature = p384::ecdsa::Signature::from_der(signature).map_err(|e| e.to_string())?;
+                    key.verify_prehash(&digest, &signature).is_ok()
+                }
+                _ => return Err("Unsupported elliptic curve".to_string()),
+            }
+        }
+        SignatureScheme::Dsa => return Err("DSA signatures are not supported".to_string()),
+    };
+    if verified { Ok(()) } else { Err("Signature does not verify".to_string()) }
+}
+
+/// The offsets of the ZIP sections an APK signature covers.
+struct ApkSections<'a> {
+    data: &'a [u8],
+    entries_end: usize,
+    central_directory: usize,
+    eocd: usize,
+    blocks: Vec<(u32, &'a [u8])>,
+    digests: HashMap<ContentDigest, Vec<u8>>,
+}
+
+impl<'a> ApkSections<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let last = data.len().checked_sub(EOCD_SIZE)?;
+        let eocd = (last.saturating_sub(u16::MAX as usize)..=last).rev()
+            .find(|offset| u32_at(data, *offset) == Some(EOCD_MAGIC) && u16_at(data, offset + 20).map(|length| offset + EOCD_SIZE + length as usize) == Some(data.len()))?;
+        let central_directory = u32_at(data, eocd + 16)? as usize;
+        if central_directory > eocd {
+            return None;
+        }
+
+        let mut sections = ApkSections { data, entries_end: central_directory, central_directory, eocd, blocks: Vec::new(), digests: HashMap::new() };
+        // The signing block sits right before the central directory: u64 size, pairs, u64 size, magic
+        if central_directory >= 32 && data.get(central_directory - 16..central_directory) == Some(SIGNING_BLOCK_MAGIC) {
+            let size = u64::from_le_bytes(data[central_directory - 24..central_directory - 16].try_into().ok()?) as usize;
+            let start = central_directory.checked_sub(size.checked_add(8)?)?;
+            if u64::from_le_bytes(data.get(start..start + 8)?.try_into().ok()?) as usize == size {
+                let mut pairs = data.get(start + 8..central_directory - 24)?;
+                while pairs.len() >= 12 {
+                    let length = u64::from_le_bytes(pairs[..8].try_into().ok()?) as usize;
+                    let Some(pair) = pairs.get(8..8usize.saturating_add(length)).filter(|pair| pair.len() >= 4) else {
+                        break;
+                    };
+                    sections.blocks.push((u32_at(pair, 0)?, &pair[4..]));
+                    pairs = &pairs[8 + length..];
+                }
+                sections.entries_end = start;
+            }
+        }
+        Some(sections)
+    }
+
+    fn block(&self, id: u32) -> Option<&'a [u8]> {
+        self.blocks.iter().find(|(block_id, _)| *block_id == id).map(|(_, value)| *value)
+    }
+
+    /// Digest over 1 MiB chunks of the entries, central directory and EOCD, as used by v2 and later schemes.
+    fn content_digest(&mut self, kind: ContentDigest) -> Option<Vec<u8>> {
+        fn chunked<D: Digest>(sections: &[&[u8]]) -> Vec<u8> {
+            let chunks: Vec<&[u8]> = sections.iter().flat_map(|section| section.chunks(CHUNK_SIZE)).collect();
+            let mut top = D::new();
+            top.update([0x5a]);
+            top.update((chunks.len() as u32).to_le_bytes());
+            for chunk in chunks {
+                let mut hasher = D::new();
+                hasher.update([0xa5]);
+                hasher.update((chunk.len() as u32).to_le_bytes());
+                hasher.update(chunk);
+                top.update(hasher.finalize());
+            }
+            top.finalize().to_vec()
+        }
+
+        if let Some(digest) = self.digests.get(&kind) {
+            return Some(digest.clone());
+        }
+
+        // The EOCD is digested as if the central directory started where the signing block does
+        let mut eocd = self.data[self.eocd..].to_vec();
+        eocd[16..20].copy_from_slice(&(self.entries_end as u32).to_le_bytes());
+        let sections = [&self.data[..self.entries_end], &self.data[self.central_directory..self.eocd], &eocd[..]];
+        let digest = match kind {
+            ContentDigest::ChunkedSha256 => chunked::<Sha256>(&sections),
+            ContentDigest::ChunkedSha512 => chunked::<Sha512>(&sections),
+            ContentDigest::Verity => return None,
+        };
+        self.digests.insert(kind, digest.clone());
+        Some(digest)
+    }
+}
+
+/// A v2/v3 signer along with the values needed to cross check schemes.
+struct BlockSigner {
+    signer: Signer,
+    certificate: Option<Vec<u8>>,
+    attributes: Vec<(u32, Vec<u8>)>,
+}
+
+fn verify_block_signer(data: &[u8], sections: &mut ApkSections, v3: bool) -> BlockSigner {
+    let mut result = BlockSigner {
+        signer: Signer { certificate: None, min_sdk: None, max_sdk: None, algorithms: Vec::new(), verified: false, errors: Vec::new() },
+        certificate: None,
+        attributes: Vec::new(),
+    };
+    if let Err(error) = check_block_signer(data, sections, v3, &mut result) {
+        result.signer.errors.push(error);
+    }
+    result.signer.verified = result.signer.errors.is_empty();
+    result
+}
+
+fn check_block_signer(data: &[u8], sections: &mut ApkSections, v3: bool, result: &mut BlockSigner) -> Result<(), String> {
+    let malformed = || "Malformed signer".to_string();
+    let mut reader = Reader::new(data);
+    let signed_data = reader.prefixed().ok_or_else(malformed)?;
+    let sdk_range = if v3 { Some((reader.u32().ok_or_else(malformed)?, reader.u32().ok_or_else(malformed)?)) } else { None };
+    let signatures = Reader::new(reader.prefixed().ok_or_else(malformed)?).items().ok_or_else(malformed)?;
+    let public_key = reader.prefixed().ok_or_else(malformed)?;
+
+    let mut signed = Reader::new(signed_data);
+    let digests = Reader::new(signed.prefixed().ok_or_else(malformed)?).items().ok_or_else(malformed)?;
+    let certificates = Reader::new(signed.prefixed().ok_or_else(malformed)?).items().ok_or_else(malformed)?;
+    let signed_sdk_range = if v3 { Some((signed.u32().ok_or_else(malformed)?, signed.u32().ok_or_else(malformed)?)) } else { None };
+    for attribute in Reader::new(signed.prefixed().ok_or_else(malformed)?).items().ok_or_else(malformed)? {
+        let id = u32_at(attribute, 0).ok_or_else(malformed)?;
+        result.attributes.push((id, attribute[4..].to_vec()));
+    }
+
+    if let Some((min_sdk, max_sdk)) = sdk_range {
+        result.signer.min_sdk = Some(min_sdk);
+        result.signer.max_sdk = Some(max_sdk);
+    }
+    if let Some(certificate) = certificates.first() {
+        result.signer.certificate = certificate_info(certificate);
+        result.certificate = Some(certificate.to_vec());
+    }
+    if sdk_range != signed_sdk_range {
+        result.signer.errors.push("SDK version range does not match the signed data".to_string());
+    }
+
+    let certificate = certificates.first().ok_or("No certificates".to_string())?;
+    let spki = cert::tbs_fields(certificate).and_then(|fields| fields.get(5).map(|spki| spki.raw)).ok_or("Invalid certificate".to_string())?;
+    if spki != public_key {
+        result.signer.errors.push("Public key does not match the certificate".to_string());
+    }
+
+    let mut signature_ids = Vec::new();
+    for signature in signatures {
+        let mut reader = Reader::new(signature);
+        let id = reader.u32().ok_or_else(malformed)?;
+        let value = reader.prefixed().ok_or_else(malformed)?;
+        signature_ids.push(id);
+        let Some(algorithm) = algorithm(id) else {
+            result.signer.errors.push(format!("Unknown signature algorithm 0x{:04x}", id));
+            continue;
+        };
+        result.signer.algorithms.push(algorithm.name.to_string());
+        if let Err(error) = verify_signature(public_key, algorithm.scheme, algorithm.hash, signed_data, value) {
+            result.signer.errors.push(format!("{}: {}", algorithm.name, error));
+        }
+    }
+    if signature_ids.is_empty() {
+        return Err("No signatures".to_string());
+    }
+
+    let mut digest_ids = Vec::new();
+    let mut content_verified = false;
+    for digest in digests {
+        let mut reader = Reader::new(digest);
+        let id = reader.u32().ok_or_else(malformed)?;
+        let value = reader.prefixed().ok_or_else(malformed)?;
+        digest_ids.push(id);
+        let Some(expected) = algorithm(id).and_then(|algorithm| sections.content_digest(algorithm.content)) else {
+            continue;
+        };
+        if expected != value {
+            result.signer.errors.push(format!("{} content digest does not match, the APK was modified after signing", algorithm(id).map(|algorithm| algorithm.name).unwrap_or_default()));
+        }
+        content_verified = true;
+    }
+    if digest_ids != signature_ids {
+        result.signer.errors.push("Signature algorithms do not match the digests".to_string());
+    }
+    if !content_verified {
+        result.signer.errors.push("No supported content digest to check".to_string());
+    }
+    Ok(())
+}
+
+fn verify_block(sections: &mut ApkSections, id: u32, scheme: &str) -> Option<(SchemeResult, Vec<BlockSigner>)> {
+    let block = sections.block(id)?;
+    let mut result = SchemeResult { scheme: scheme.to_string(), verified: false, signers: Vec::new(), errors: Vec::new() };
+    let mut signers = Vec::new();
+    match Reader::new(block).prefixed().and_then(|signers| Reader::new(signers).items()) {
+        Some(items) if !items.is_empty() => {
+            for item in items {
+                let signer = verify_block_signer(item, sections, id != V2_BLOCK_ID);
+                result.signers.push(signer.signer.clone());
+                signers.push(signer);
+            }
+        }
+        Some(_) => result.errors.push("No signers".to_string()),
+        None => result.errors.push("Malformed signature block".to_string()),
+    }
+    result.verified = result.errors.is_empty() && result.signers.iter().all(|signer| signer.verified);
+    Some((result, signers))
+}
+
+const CAPABILITIES: &[(u32, &str)] = &[(1, "installed-data"), (2, "shared-uid"), (4, "permission"), (8, "rollback"), (16, "auth")];
+
+/// Reads a proof-of-rotation attribute, each certificate is signed by the one before it.
+fn read_lineage(value: &[u8], errors: &mut Vec<String>) -> Vec<(Vec<u8>, LineageEntry)> {
+    let mut lineage: Vec<(Vec<u8>, LineageEntry)> = Vec::new();
+    let mut reader = Reader::new(value);
+    if reader.u32() != Some(1) {
+        errors.push("Unsupported proof-of-rotation version".to_string());
+        return lineage;
+    }
+
+    let mut last_algorithm = None;
+    while !reader.data.is_empty() {
+        let node = (|| {
+            let mut node = Reader::new(reader.prefixed()?);
+            let signed_data = node.prefixed()?;
+            let flags = node.u32()?;
+            let algorithm_id = node.u32()?;
+            let signature = node.prefixed()?;
+            let mut signed = Reader::new(signed_data);
+            let certificate = signed.prefixed()?;
+            let signed_algorithm = signed.u32()?;
+            Some((signed_data, flags, algorithm_id, signature, certificate, signed_algorithm))
+        })();
+        let Some((signed_data, flags, algorithm_id, signature, certificate, signed_algorithm)) = node else {
+            errors.push("Malformed proof-of-rotation".to_string());
+            break;
+        };
+        let Some(info) = certificate_info(certificate) else {
+            errors.push("Invalid certificate in proof-of-rotation".to_string());
+            break;
+        };
+
+        let mut verified = true;
+        if let Some((previous, _)) = lineage.last() {
+            let spki = cert::tbs_fields(previous).and_then(|fields| fields.get(5).map(|spki| spki.raw.to_vec())).unwrap_or_default();
+            let result = match last_algorithm.and_then(algorithm) {
+                _ if last_algorithm != Some(signed_algorithm) => Err("Signature algorithm does not match the signed data".to_string()),
+                Some(algorithm) => verify_signature(&spki, algorithm.scheme, algorithm.hash, signed_data, signature),
+                None => Err("Unknown signature algorithm".to_string()),
+            };
+            if let Err(error) = result {
+                errors.push(format!("Rotation to {} is not signed by {}: {}", info.subject, lineage.last().map(|(_, entry)| entry.certificate.subject.as_str()).unwrap_or_default(), error));
+                verified = false;
+            }
+        }
+        last_algorithm = Some(algorithm_id);
+        lineage.push((certificate.to_vec(), LineageEntry {
+            certificate: info,
+            capabilities: CAPABILITIES.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| name.to_string()).collect(),
+            verified,
+        }));
+    }
+    lineage
+}
+
+/// A manifest or signature file section, `raw` includes the terminating blank line.
+struct Section<'a> {
+    raw: &'a [u8],
+    attributes: Vec<(String, String)>,
+}
+
+impl Section<'_> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// Finds a `<algorithm><suffix>` digest attribute, preferring the strongest known algorithm.
+    fn digest(&self, suffix: &str) -> Option<(HashAlgorithm, Vec<u8>)> {
+        self.attributes.iter()
+            .filter_map(|(key, value)| {
+                let name = key.get(..key.len().checked_sub(suffix.len())?).filter(|_| key[key.len() - suffix.len()..].eq_ignore_ascii_case(suffix))?;
+                Some((HashAlgorithm::from_jar_name(name)?, general_purpose::STANDARD.decode(value).ok()?))
+            })
+            .max_by_key(|(hash, _)| *hash as u8)
+    }
+}
+
+fn parse_sections(data: &[u8]) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let (mut start, mut offset) = (0, 0);
+    while offset < data.len() {
+        let end = data[offset..].iter().position(|byte| *byte == b'\n' || *byte == b'\r').map_or(data.len(), |position| offset + position);
+        let mut next = end;
+        if data.get(next) == Some(&b'\r') {
+            next += 1;
+        }
+        if data.get(next) == Some(&b'\n') {
+            next += 1;
+        }
+
+        let line = &data[offset..end];
+        if line.is_empty() {
+            if !attributes.is_empty() {
+                sections.push(Section { raw: &data[start..next], attributes: std::mem::take(&mut attributes) });
+            }
+            start = next;
+        } else if line[0] == b' ' {
+            // Lines are wrapped at 72 bytes, continuations start with a single space
+            if let Some((_, value)) = attributes.last_mut() {
+                value.push_str(&String::from_utf8_lossy(&line[1..]));
+            }
+        } else if let Some(colon) = line.windows(2).position(|pair| pair == b": ") {
+            attributes.push((String::from_utf8_lossy(&line[..colon]).to_string(), String::from_utf8_lossy(&line[colon + 2..]).to_string()));
+        }
+        offset = next;
+    }
+    if !attributes.is_empty() {
+        sections.push(Section { raw: &data[start..], attributes });
+    }
+    sections
+}
+
+struct Pkcs7Signer<'a> {
+    certificate: Option<&'a [u8]>,
+    digest_algorithm: Option<HashAlgorithm>,
+    signed_attributes: Option<Tlv<'a>>,
+    signature_algorithm: String,
+    signature: &'a [u8],
+}
+
+fn parse_pkcs7(data: &[u8]) -> Option<Pkcs7Signer<'_>> {
+    let (content_info, _) = read_tlv(data)?;
+    let explicit = *children(content_info.content).get(1).filter(|tlv| tlv.tag == 0xa0)?;
+    let (signed_data, _) = read_tlv(explicit.content)?;
+    // version, digestAlgorithms, encapContentInfo, [0] certificates, [1] crls, signerInfos
+    let fields = children(signed_data.content);
+    let certificates: Vec<&[u8]> = fields.iter().find(|field| field.tag == 0xa0).map(|field| children(field.content).iter().map(|certificate| certificate.raw).collect()).unwrap_or_default();
+    let signer_info = *children(fields.last().filter(|field| field.tag == 0x31)?.content).first()?;
+
+    // version, sid, digestAlgorithm, [0] signedAttrs, signatureAlgorithm, signature
+    let info = children(signer_info.content);
+    let sid = children(info.get(1)?.content);
+    let (signed_attributes, index) = match info.get(3)?.tag {
+        0xa0 => (Some(info[3]), 4),
+        _ => (None, 3),
+    };
+    // Match the issuer and serial number, falling back to the first certificate for subject key ids
+    let certificate = certificates.iter().copied()
+        .find(|certificate| cert::tbs_fields(certificate).is_some_and(|fields| {
+            fields.first().map(|serial| serial.content) == sid.get(1).map(|serial| serial.content) && fields.get(2).map(|issuer| issuer.raw) == sid.first().map(|issuer| issuer.raw)
+        }))
+        .or(certificates.first().copied());
+
+    Some(Pkcs7Signer {
+        certificate,
+        digest_algorithm: first_oid(info.get(2)?).and_then(|oid| HashAlgorithm::from_oid(&oid)),
+        signed_attributes,
+        signature_algorithm: first_oid(info.get(index)?)?,
+        signature: info.get(index + 1)?.content,
+    })
+}
+
+fn verify_jar_signer(signature_file: &[u8], block: &[u8], manifest: &[u8], sections: &ApkSections) -> Signer {
+    let mut signer = Signer { certificate: None, min_sdk: None, max_sdk: None, algorithms: Vec::new(), verified: false, errors: Vec::new() };
+    let Some(pkcs7) = parse_pkcs7(block) else {
+        signer.errors.push("Malformed PKCS#7 signature block".to_string());
+        return signer;
+    };
+    signer.certificate = pkcs7.certificate.and_then(certificate_info);
+
+    let scheme = match pkcs7.signature_algorithm.as_str() {
+        "1.2.840.113549.1.1.1" | "1.2.840.113549.1.1.5" | "1.2.840.113549.1.1.11" | "1.2.840.113549.1.1.13" => Some(SignatureScheme::RsaPkcs1),
+        "1.2.840.10045.2.1" | "1.2.840.10045.4.1" | "1.2.840.10045.4.3.2" | "1.2.840.10045.4.3.4" => Some(SignatureScheme::Ecdsa),
+        "1.2.840.10040.4.1" | "1.2.840.10040.4.3" | "2.16.840.1.101.3.4.3.2" => Some(SignatureScheme::Dsa),
+        _ => None,
+    };
+    match (scheme, pkcs7.digest_algorithm, pkcs7.certificate) {
+        (Some(scheme), Some(hash), Some(certificate)) => {
+            let key = match scheme {
+                SignatureScheme::RsaPss | SignatureScheme::RsaPkcs1 => "RSA",
+                SignatureScheme::Ecdsa => "ECDSA",
+                SignatureScheme::Dsa => "DSA",
+            };
+            signer.algorithms.push(format!("{} with {}", key, hash.name()));
+            // With signed attributes the signature covers their DER encoding as a SET instead of the file
+            let message = match pkcs7.signed_attributes {
+                Some(attributes) => {
+                    let message_digest = children(attributes.content).iter()
+                        .find(|attribute| first_oid(attribute).as_deref() == Some(OID_MESSAGE_DIGEST))
+                        .and_then(|attribute| children(attribute.content).get(1).and_then(|set| children(set.content).first().map(|value| value.content.to_vec())));
+                    if message_digest != Some(hash.digest(signature_file)) {
+                        signer.errors.push("Signed attributes do not match the signature file".to_string());
+                    }
+                    let mut encoded = attributes.raw.to_vec();
+                    encoded[0] = 0x31;
+                    encoded
+                }
+                None => signature_file.to_vec(),
+            };
+            let spki = cert::tbs_fields(certificate).and_then(|fields| fields.get(5).map(|spki| spki.raw)).unwrap_or_default();
+            if let Err(error) = verify_signature(spki, scheme, hash, &message, pkcs7.signature) {
+                signer.errors.push(error);
+            }
+        }
+        (_, _, None) => signer.errors.push("No signer certificate".to_string()),
+        _ => signer.errors.push(format!("Unsupported signature algorithm {}", pkcs7.signature_algorithm)),
+    }
+
+    let signature_sections = parse_sections(signature_file);
+    let Some(main) = signature_sections.first() else {
+        signer.errors.push("Empty signature file".to_string());
+        return signer;
+    };
+
+    // Prefer the digest of the whole manifest, older signers only list per-entry section digests
+    let manifest_matches = main.digest("-Digest-Manifest").is_some_and(|(hash, digest)| hash.digest(manifest) == digest);
+    if !manifest_matches {
+        let manifest_sections = parse_sections(manifest);
+        for section in &signature_sections[1..] {
+            let Some(name) = section.attribute("Name") else {
+                continue;
+            };
+            let matches = section.digest("-Digest").is_some_and(|(hash, digest)| {
+                manifest_sections.iter().any(|entry| entry.attribute("Name") == Some(name) && hash.digest(entry.raw) == digest)
+            });
+            if !matches {
+                signer.errors.push(format!("{} does not match the manifest", name));
+            }
+        }
+    }
+
+    // Guards against stripping the newer signatures to downgrade to v1
+    if let Some(signed) = main.attribute("X-Android-APK-Signed") {
+        for scheme in signed.split(',').map(|scheme| scheme.trim()) {
+            let id = match scheme {
+                "2" => V2_BLOCK_ID,
+                "3" => V3_BLOCK_ID,
+                _ => continue,
+            };
+            if sections.block(id).is_none() {
+                signer.errors.push(format!("APK was signed with v{} but the signature was stripped", scheme));
+            }
+        }
+    }
+
+    signer.verified = signer.errors.is_empty();
+    signer
+}
+
+fn verify_jar(data: &[u8], sections: &ApkSections) -> Option<SchemeResult> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+    let read = |archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str| -> Option<Vec<u8>> {
+        let mut content = Vec::new();
+        archive.by_name(name).ok()?.read_to_end(&mut content).ok()?;
+        Some(content)
+    };
+
+    let names: Vec<String> = archive.file_names().map(|name| name.to_string()).collect();
+    let signature_files: Vec<&String> = names.iter()
+        .filter(|name| name.starts_with("META-INF/") && name.matches('/').count() == 1 && name.to_ascii_uppercase().ends_with(".SF"))
+        .collect();
+    if signature_files.is_empty() {
+        return None;
+    }
+
+    let mut result = SchemeResult { scheme: "v1".to_string(), verified: false, signers: Vec::new(), errors: Vec::new() };
+    let Some(manifest) = read(&mut archive, "META-INF/MANIFEST.MF") else {
+        result.errors.push("Missing META-INF/MANIFEST.MF".to_string());
+        return Some(result);
+    };
+
+    for signature_file in signature_files {
+        let stem = &signature_file[..signature_file.len() - 3];
+        let block = ["RSA", "EC", "DSA"].iter().find_map(|extension| names.iter().find(|name| name.eq_ignore_ascii_case(&format!("{}.{}", stem, extension))));
+        let (Some(content), Some(block)) = (read(&mut archive, signature_file), block.and_then(|block| read(&mut archive, block))) else {
+            result.errors.push(format!("{} has no signature block", signature_file));
+            continue;
+        };
+        result.signers.push(verify_jar_signer(&content, &block, &manifest, sections));
+    }
+
+    let entries: HashMap<String, (HashAlgorithm, Vec<u8>)> = parse_sections(&manifest).iter()
+        .filter_map(|section| Some((section.attribute("Name")?.to_string(), section.digest("-Digest")?)))
+        .collect();
+    for (name, (hash, digest)) in &entries {
+        match read(&mut archive, name) {
+            Some(content) if hash.digest(&content) == *digest => {}
+            Some(_) => result.errors.push(format!("{} was modified after signing", name)),
+            None => result.errors.push(format!("{} is listed in the manifest but missing", name)),
+        }
+    }
+    for name in &names {
+        if !name.ends_with('/') && !name.starts_with("META-INF/") && !entries.contains_key(name) {
+            result.errors.push(format!("{} is not protected by the signature", name));
+        }
+    }
+
+    result.verified = !result.signers.is_empty() && result.errors.is_empty() && result.signers.iter().all(|signer| signer.verified);
+    Some(result)
+}
+
+/// Verifies every signature scheme present in the APK and cross checks their signers.
+pub fn verify(data: &[u8]) -> Result<SignatureReport, String> {
+    let mut sections = ApkSections::parse(data).ok_or("Not a valid APK".to_string())?;
+    let mut report = SignatureReport { verified: false, schemes: Vec::new(), lineage: Vec::new(), mismatches: Vec::new() };
+
+    let v1 = verify_jar(data, &sections);
+    let v2 = verify_block(&mut sections, V2_BLOCK_ID, "v2");
+    let v3 = verify_block(&mut sections, V3_BLOCK_ID, "v3");
+    let v31 = verify_block(&mut sections, V31_BLOCK_ID, "v3.1");
+
+    // The newest lineage describes the whole rotation history
+    let mut lineage_errors = Vec::new();
+    let lineage = [&v31, &v3].iter()
+        .filter_map(|scheme| scheme.as_ref())
+        .flat_map(|(_, signers)| signers.iter())
+        .find_map(|signer| signer.attributes.iter().find(|(id, _)| *id == PROOF_OF_ROTATION_ATTR_ID).map(|(_, value)| (signer, read_lineage(value, &mut lineage_errors))));
+    if let Some((signer, lineage)) = &lineage {
+        if lineage.last().map(|(certificate, _)| certificate) != signer.certificate.as_ref() {
+            report.mismatches.push("Proof-of-rotation does not end with the signer certificate".to_string());
+        }
+    }
+    report.mismatches.extend(lineage_errors);
+    let in_lineage = |certificate: &Vec<u8>| lineage.as_ref().is_some_and(|(_, lineage)| lineage.iter().any(|(node, _)| node == certificate));
+
+    if let Some((_, v2_signers)) = &v2 {
+        let stripped_v3 = v2_signers.iter().any(|signer| signer.attributes.iter().any(|(id, value)| *id == STRIPPING_PROTECTION_ATTR_ID && u32_at(value, 0) == Some(3)));
+        if stripped_v3 && v3.is_none() {
+            report.mismatches.push("APK was signed with v3 but the signature was stripped".to_string());
+        }
+    }
+
+    if let Some((_, v3_signers)) = &v3 {
+        let rotation = v3_signers.iter().find_map(|signer| signer.attributes.iter().find(|(id, _)| *id == ROTATION_MIN_SDK_ATTR_ID).and_then(|(_, value)| u32_at(value, 0)));
+        if let (Some(min_sdk), None) = (rotation, &v31) {
+            report.mismatches.push(format!("APK was signed with v3.1 for SDK {} and later but the signature was stripped", min_sdk));
+        }
+
+        // v2 and v3.1 may only use a key from the rotation history of the v3 signer
+        if let Some((_, v2_signers)) = &v2 {
+            for certificate in v2_signers.iter().filter_map(|signer| signer.certificate.as_ref()) {
+                if !v3_signers.iter().any(|signer| signer.certificate.as_ref() == Some(certificate)) && !in_lineage(certificate) {
+                    report.mismatches.push("v2 signer is not part of the v3 signing history".to_string());
+                }
+            }
+        }
+        if let Some((_, v31_signers)) = &v31 {
+            for certificate in v3_signers.iter().filter_map(|signer| signer.certificate.as_ref()) {
+                if !v31_signers.iter().any(|signer| signer.certificate.as_ref() == Some(certificate)) && !in_lineage(certificate) {
+                    report.mismatches.push("v3 signer is not part of the v3.1 signing history".to_string());
+                }
+            }
+        }
+    }
+
+    if let (Some(v1), Some((v2, _))) = (&v1, &v2) {
+        let fingerprints = |scheme: &SchemeResult| {
+            let mut fingerprints: Vec<String> = scheme.signers.iter().filter_map(|signer| signer.certificate.as_ref().map(|certificate| certificate.sha256.clone())).collect();
+            fingerprints.sort();
+            fingerprints
+        };
+        if fingerprints(v1) != fingerprints(v2) {
+            report.mismatches.push("v1 and v2 signers differ".to_string());
+        }
+    }
+
+    report.lineage = lineage.map(|(_, lineage)| lineage.into_iter().map(|(_, entry)| entry).collect()).unwrap_or_default();
+    report.schemes = v1.into_iter().chain([v2, v3, v31].into_iter().flatten().map(|(scheme, _)| scheme)).collect();
+    report.verified = !report.schemes.is_empty() && report.mismatches.is_empty() && report.schemes.iter().all(|scheme| scheme.verified);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fixtures are signed by apksigner, "Old Signer" is RSA 2048 and "New Signer" is EC P-256
+    const V1: &[u8] = include_bytes!("../tests/fixtures/v1.apk");
+    const V2_V3: &[u8] = include_bytes!("../tests/fixtures/v2v3.apk");
+    const ROTATED: &[u8] = include_bytes!("../tests/fixtures/rotated.apk");
+    const TAMPERED: &[u8] = include_bytes!("../tests/fixtures/tampered.apk");
+
+    fn schemes(report: &SignatureReport) -> Vec<&str> {
+        report.schemes.iter().map(|scheme| scheme.scheme.as_str()).collect()
+    }
+
+    fn subject(scheme: &SchemeResult) -> &str {
+        scheme.signers[0].certificate.as_ref().map(|certificate| certificate.subject.as_str()).unwrap_or_default()
+    }
+
+    #[test]
+    fn verifies_v1_only() {
+        let report = verify(V1).unwrap();
+        assert!(report.verified);
+        assert_eq!(schemes(&report), ["v1"]);
+        assert_eq!(subject(&report.schemes[0]), "CN=Old Signer, O=Tuyu, C=ID");
+        assert!(report.lineage.is_empty());
+    }
+
+    #[test]
+    fn verifies_v2_and_v3() {
+        let report = verify(V2_V3).unwrap();
+        assert!(report.verified);
+        assert_eq!(schemes(&report), ["v2", "v3"]);
+        assert!(report.schemes.iter().all(|scheme| scheme.verified && subject(scheme) == "CN=Old Signer, O=Tuyu, C=ID"));
+        assert!(report.mismatches.is_empty());
+
+        let summary = report.summary();
+        assert_eq!(summary.schemes, ["v2", "v3"]);
+        assert!(!summary.rotated);
+    }
+
+    #[test]
+    fn verifies_rotated_key() {
+        let report = verify(ROTATED).unwrap();
+        assert!(report.verified);
+        assert_eq!(schemes(&report), ["v2", "v3", "v3.1"]);
+        // Older platforms still see the previous key, v3.1 carries the new one
+        assert_eq!(subject(&report.schemes[1]), "CN=Old Signer, O=Tuyu, C=ID");
+        assert_eq!(subject(&report.schemes[2]), "CN=New Signer, OU=Dev, O=Tuyu");
+        assert_eq!(report.lineage.len(), 2);
+        assert!(report.lineage.iter().all(|entry| entry.verified));
+
+        let summary = report.summary();
+        assert!(summary.rotated);
+        assert_eq!(summary.signer.map(|signer| signer.subject).as_deref(), Some("CN=New Signer, OU=Dev, O=Tuyu"));
+    }
+
+    #[test]
+    fn rejects_tampered_content() {
+        let report = verify(TAMPERED).unwrap();
+        assert!(!report.verified);
+        for scheme in &report.schemes {
+            assert!(!scheme.verified);
+            assert!(scheme.signers[0].errors.iter().any(|error| error.contains("content digest does not match")), "{:?}", scheme.signers[0].errors);
+        }
+    }
+
+    #[test]
+    fn unsigned_apk_has_no_schemes() {
+        let mut apk = Vec::new();
+        let mut writer = zip::ZipWriter::new(Cursor::new(&mut apk));
+        writer.start_file("AndroidManifest.xml", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.finish().unwrap();
+
+        let report = verify(&apk).unwrap();
+        assert!(!report.verified);
+        assert!(report.schemes.is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+//! Decoder for the compiled resource table (resources.arsc) of an APK.
+
+use std::collections::HashMap;
+
+use crate::axml::{format_value, u16_at, u32_at, StringPool, TYPE_DYNAMIC_REFERENCE, TYPE_INT_BOOLEAN, TYPE_INT_COLOR_ARGB8, TYPE_INT_COLOR_RGB4, TYPE_INT_DEC, TYPE_INT_HEX, TYPE_REFERENCE, TYPE_STRING};
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_TABLE_TYPE: u16 = 0x0002;
+const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+
+const FLAG_SPARSE: u8 = 0x01;
+const FLAG_OFFSET16: u8 = 0x02;
+const ENTRY_FLAG_COMPLEX: u16 = 0x0001;
+const ENTRY_FLAG_COMPACT: u16 = 0x0008;
+const NO_ENTRY: u32 = 0xffffffff;
+
+pub const DENSITY_DEFAULT: u16 = 0;
+pub const DENSITY_MEDIUM: u16 = 160;
+pub const DENSITY_ANY: u16 = 0xfffe;
+pub const DENSITY_NONE: u16 = 0xffff;
+
+/// A resource configuration, both the qualifiers of a table entry and the device config to resolve for.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceConfig {
+    pub language: Option<String>,
+    pub country: Option<String>,
+    pub density: u16,
+    pub sdk: u16,
+    pub night: Option<bool>,
+}
+
+impl ResourceConfig {
+    fn parse(config: &[u8]) -> ResourceConfig {
+        let byte = |offset: usize| config.get(offset).copied().unwrap_or(0);
+        let night = match byte(29) & 0x30 {
+            0x10 => Some(false),
+            0x20 => Some(true),
+            _ => None,
+        };
+        ResourceConfig {
+            language: unpack_locale(byte(8), byte(9), b'a'),
+            country: unpack_locale(byte(10), byte(11), b'0'),
+            density: u16_at(config, 14).unwrap_or(0),
+            sdk: u16_at(config, 24).unwrap_or(0),
+            night,
+        }
+    }
+
+    /// The qualifier string as used in res/ folder names, e.g. `en-rUS-night-xhdpi-v26`.
+    pub fn qualifiers(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(language) = &self.language {
+            parts.push(language.clone());
+        }
+        if let Some(country) = &self.country {
+            parts.push(format!("r{}", country));
+        }
+        match self.night {
+            Some(true) => parts.push("night".to_string()),
+            Some(false) => parts.push("notnight".to_string()),
+            None => {}
+        }
+        match self.density {
+            DENSITY_DEFAULT => {}
+            120 => parts.push("ldpi".to_string()),
+            160 => parts.push("mdpi".to_string()),
+            213 => parts.push("tvdpi".to_string()),
+            240 => parts.push("hdpi".to_string()),
+            320 => parts.push("xhdpi".to_string()),
+            480 => parts.push("xxhdpi".to_string()),
+            640 => parts.push("xxxhdpi".to_string()),
+            DENSITY_ANY => parts.push("anydpi".to_string()),
+            DENSITY_NONE => parts.push("nodpi".to_string()),
+            density => parts.push(format!("{}dpi", density)),
+        }
+        if self.sdk > 0 {
+            parts.push(format!("v{}", self.sdk));
+        }
+        parts.join("-")
+    }
+
+    /// Whether an entry with these qualifiers can be used on a device with the `device` config.
+    fn matches(&self, device: &ResourceConfig) -> bool {
+        if self.language.is_some() && self.language != device.language {
+            return false;
+        }
+        if self.country.is_some() && self.country != device.country {
+            return false;
+        }
+        if self.sdk > 0 && device.sdk > 0 && self.sdk > device.sdk {
+            return false;
+        }
+        if let Some(night) = self.night {
+            if night != device.night.unwrap_or(false) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Ranks a matching entry, higher is more specific for the device.
+    fn score(&self, device: &ResourceConfig) -> (u8, u8, u32, u16) {
+        let locale = self.language.is_some() as u8 + self.country.is_some() as u8;
+        let night = self.night.is_some() as u8;
+        // anydpi always wins, otherwise the closest density at or above the device, then the closest below
+        let density = match (self.density, device.density) {
+            (DENSITY_ANY, _) => u32::MAX,
+            (DENSITY_NONE, _) => 1,
+            (density, 0) => density_or_medium(density) as u32,
+            (density, wanted) => {
+                let density = density_or_medium(density) as u32;
+                if density >= wanted as u32 { 0x20000 - density } else { density }
+            }
+        };
+        (locale, night, density, self.sdk)
+    }
+}
+
+pub fn density_or_medium(density: u16) -> u16 {
+    if density == DENSITY_DEFAULT { DENSITY_MEDIUM } else { density }
+}
+
+/// Locales are two ASCII letters, or three 5 bit letters packed into both bytes.
+fn unpack_locale(first: u8, second: u8, base: u8) -> Option<String> {
+    if first == 0 && second == 0 {
+        return None;
+    }
+    if first & 0x80 == 0 {
+        return Some(String::from_utf8_lossy(&[first, second]).to_string());
+    }
+    let letters = [second & 0x1f, ((second & 0xe0) >> 5) | ((first & 0x03) << 3), (first & 0x7c) >> 2];
+    Some(letters.iter().map(|letter| (base + letter) as char).collect())
+}
+
+#[derive(Debug, Clone)]
+pub enum EntryValue {
+    Simple { data_type: u8, data: u32 },
+    Bag { items: Vec<(u32, u8, u32)> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub config: ResourceConfig,
+    pub value: EntryValue,
+}
+
+#[derive(Debug, serde::Serialize, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ResourceValue {
+    String(String),
+    File(String),
+    Color(String),
+    Integer(i64),
+    Boolean(bool),
+    Bag(Vec<(u32, String)>),
+    Other(String),
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct ResolvedResource {
+    pub id: u32,
+    pub name: String,
+    pub config: String,
+    pub value: ResourceValue,
+}
+
+#[derive(Debug, Default)]
+pub struct ResourceTable {
+    strings: StringPool,
+    names: HashMap<u32, String>,
+    entries: HashMap<u32, Vec<Entry>>,
+}
+
+impl ResourceTable {
+    pub fn parse(data: &[u8]) -> Option<ResourceTable> {
+        if u16_at(data, 0)? != RES_TABLE_TYPE {
+            return None;
+        }
+        let header_size = u16_at(data, 2)? as usize;
+        let total_size = (u32_at(data, 4)? as usize).min(data.len());
+
+        let mut table = ResourceTable::default();
+        for (chunk_type, chunk) in chunks(data, header_size, total_size) {
+            match chunk_type {
+                RES_STRING_POOL_TYPE => table.strings = StringPool::parse(chunk)?,
+                RES_TABLE_PACKAGE_TYPE => table.parse_package(chunk)?,
+                _ => {}
+            }
+        }
+        Some(table)
+    }
+
+    fn parse_package(&mut self, package: &[u8]) -> Option<()> {
+        let header_size = u16_at(package, 2)? as usize;
+        let package_id = u32_at(package, 8)?;
+        let type_strings = StringPool::parse(package.get(u32_at(package, 268)? as usize..)?)?;
+        let key_strings = StringPool::parse(package.get(u32_at(package, 276)? as usize..)?)?;
+        let type_id_offset = u32_at(package, 284).filter(|_| header_size >= 288).unwrap_or(0);
+
+        for (chunk_type, chunk) in chunks(package, header_size, package.len()) {
+            if chunk_type != RES_TABLE_TYPE_TYPE {
+                continue;
+            }
+            let Some(type_id) = chunk.get(8).copied() else { continue };
+            let flags = chunk.get(9).copied().unwrap_or(0);
+            let entry_count = u32_at(chunk, 12)? as usize;
+            let entries_start = u32_at(chunk, 16)? as usize;
+            let type_header_size = u16_at(chunk, 2)? as usize;
+            let config = ResourceConfig::parse(chunk.get(20..type_header_size).unwrap_or_default());
+            let type_name = type_strings.get((type_id as u32).wrapping_sub(1).wrapping_sub(type_id_offset)).unwrap_or_default();
+
+            // Offsets stop at the end of the chunk so a corrupt entry count can't run away
+            let offsets: Vec<Option<(u32, usize)>> = if flags & FLAG_SPARSE != 0 {
+                (0..entry_count).map_while(|i| {
+                    let index = u16_at(chunk, type_header_size + i * 4)?;
+                    let offset = u16_at(chunk, type_header_size + i * 4 + 2)?;
+                    Some(Some((index as u32, offset as usize * 4)))
+                }).collect()
+            } else if flags & FLAG_OFFSET16 != 0 {
+                (0..entry_count).map_while(|i| {
+                    let offset = u16_at(chunk, type_header_size + i * 2)?;
+                    Some((offset != 0xffff).then_some((i as u32, offset as usize * 4)))
+                }).collect()
+            } else {
+                (0..entry_count).map_while(|i| {
+                    let offset = u32_at(chunk, type_header_size + i * 4)?;
+                    Some((offset != NO_ENTRY).then_some((i as u32, offset as usize)))
+                }).collect()
+            };
+
+            for (index, offset) in offsets.into_iter().flatten() {
+                let Some(entry) = chunk.get(entries_start + offset..) else { continue };
+                let Some((key, value)) = parse_entry(entry) else { continue };
+                let id = (package_id << 24) | ((type_id as u32) << 16) | index;
+                self.names.entry(id).or_insert_with(|| format!("{}/{}", type_name, key_strings.get(key).unwrap_or_default()));
+                self.entries.entry(id).or_default().push(Entry { config: config.clone(), value });
+            }
+        }
+        Some(())
+    }
+
+    /// The `type/name` of a resource id, e.g. `string/app_name`.
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(|name| name.as_str())
+    }
+
+    pub fn entries(&self, id: u32) -> &[Entry] {
+        self.entries.get(&id).map(|entries| entries.as_slice()).unwrap_or_default()
+    }
+
+    /// Picks the entry Android would use on a device with the given config.
+    pub fn select(&self, id: u32, device: &ResourceConfig) -> Option<&Entry> {
+        let entries = self.entries(id);
+        entries.iter()
+            .filter(|entry| entry.config.matches(device))
+            .max_by_key(|entry| entry.config.score(device))
+            .or(entries.first())
+    }
+
+    /// Follows references from a resource id to the entry that holds the actual value.
+    fn select_value(&self, id: u32, device: &ResourceConfig) -> Option<(u32, &Entry)> {
+        let mut id = id;
+        // References can chain, the limit guards against cycles
+        for _ in 0..16 {
+            let entry = self.select(id, device)?;
+            match entry.value {
+                EntryValue::Simple { data_type: TYPE_REFERENCE | TYPE_DYNAMIC_REFERENCE, data } if data != 0 => id = data,
+                _ => return Some((id, entry)),
+            }
+        }
+        None
+    }
+
+    /// Resolves a resource id for the config, following references to other resources.
+    pub fn resolve(&self, id: u32, device: &ResourceConfig) -> Option<ResolvedResource> {
+        let (id, entry) = self.select_value(id, device)?;
+        Some(ResolvedResource {
+            id,
+            name: self.name(id).unwrap_or_default().to_string(),
+            config: entry.config.qualifiers(),
+            value: self.value(&entry.value),
+        })
+    }
+
+    /// Resolves a resource to a string, `None` for anything that isn't a plain value.
+    pub fn resolve_string(&self, id: u32, device: &ResourceConfig) -> Option<String> {
+        match self.resolve(id, device)?.value {
+            ResourceValue::String(value) | ResourceValue::File(value) | ResourceValue::Color(value) | ResourceValue::Other(value) => Some(value),
+            ResourceValue::Integer(value) => Some(value.to_string()),
+            ResourceValue::Boolean(value) => Some(value.to_string()),
+            ResourceValue::Bag(_) => None,
+        }
+    }
+
+    /// All file variants of a resource with their density, following references per density.
+    pub fn files(&self, id: u32) -> Vec<(u16, String)> {
+        let mut files: Vec<(u16, String)> = Vec::new();
+        for entry in self.entries(id).iter().filter(|entry| entry.config.language.is_none()) {
+            let device = ResourceConfig { density: entry.config.density, ..Default::default() };
+            let target = match entry.value {
+                EntryValue::Simple { data_type: TYPE_REFERENCE | TYPE_DYNAMIC_REFERENCE, data } => self.select_value(data, &device).map(|(_, target)| target),
+                _ => Some(entry),
+            };
+            let Some(target) = target else { continue };
+            if let ResourceValue::File(path) = self.value(&target.value) {
+                if !files.iter().any(|(_, existing)| existing == &path) {
+                    files.push((target.config.density, path));
+                }
+            }
+        }
+        files
+    }
+
+    /// The highest density bitmap for an icon resource, vector and adaptive icons are skipped.
+    pub fn best_bitmap(&self, id: u32) -> Option<String> {
+        self.files(id).into_iter()
+            .filter(|(_, path)| [".png", ".webp", ".jpg", ".jpeg"].iter().any(|ext| path.ends_with(ext)))
+            .max_by_key(|(density, _)| match *density {
+                DENSITY_NONE => DENSITY_MEDIUM,
+                density => density_or_medium(density),
+            })
+            .map(|(_, path)| path)
+    }
+
+    fn value(&self, value: &EntryValue) -> ResourceValue {
+        match *value {
+            EntryValue::Simple { data_type: TYPE_STRING, data } => {
+                let value = self.strings.get(data).unwrap_or_default().to_string();
+                // File based resources are stored as strings holding their path in the APK
+                if value.starts_with("res/") || value.starts_with("r/") {
+                    ResourceValue::File(value)
+                } else {
+                    ResourceValue::String(value)
+                }
+            }
+            EntryValue::Simple { data_type: TYPE_INT_BOOLEAN, data } => ResourceValue::Boolean(data != 0),
+            EntryValue::Simple { data_type: TYPE_INT_DEC | TYPE_INT_HEX, data } => ResourceValue::Integer(data as i32 as i64),
+            EntryValue::Simple { data_type: TYPE_INT_COLOR_ARGB8..=TYPE_INT_COLOR_RGB4, data } => ResourceValue::Color(format!("#{:08x}", data)),
+            EntryValue::Simple { data_type, data } => ResourceValue::Other(format_value(data_type, data, &self.strings)),
+            EntryValue::Bag { ref items } => ResourceValue::Bag(items.iter()
+                .map(|(name, data_type, data)| (*name, format_value(*data_type, *data, &self.strings)))
+                .collect()),
+        }
+    }
+}
+
+/// Iterates the child chunks of a chunk, stopping at the first malformed one.
+fn chunks(data: &[u8], start: usize, end: usize) -> Vec<(u16, &[u8])> {
+    let mut chunks = Vec::new();
+    let mut offset = start;
+    while offset + 8 <= end {
+        let (Some(chunk_type), Some(size)) = (u16_at(data, offset), u32_at(data, offset + 4)) else { break };
+        let size = size as usize;
+        if size < 8 || offset + size > end {
+            break;
+        }
+        chunks.push((chunk_type, &data[offset..offset + size]));
+        offset += size;
+    }
+    chunks
+}
+
+fn parse_entry(entry: &[u8]) -> Option<(u32, EntryValue)> {
+    let size = u16_at(entry, 0)? as usize;
+    let flags = u16_at(entry, 2)?;
+
+    if flags & ENTRY_FLAG_COMPACT != 0 {
+        // Compact entries store the key in the size field and the value type in the high byte of the flags
+        return Some((size as u32, EntryValue::Simple { data_type: (flags >> 8) as u8, data: u32_at(entry, 4)? }));
+    }
+
+    let key = u32_at(entry, 4)?;
+    if flags & ENTRY_FLAG_COMPLEX != 0 {
+        // Bags start with the parent style at 8, then the item count
+        let count = u32_at(entry, 12)? as usize;
+        let items = (0..count.min(entry.len() / 12))
+            .map_while(|i| {
+                let map = entry.get(size + i * 12..)?;
+                Some((u32_at(map, 0)?, *map.get(7)?, u32_at(map, 8)?))
+            })
+            .collect();
+        return Some((key, EntryValue::Bag { items }));
+    }
+
+    let value = entry.get(size..)?;
+    Some((key, EntryValue::Simple { data_type: *value.get(3)?, data: u32_at(value, 4)? }))
+}
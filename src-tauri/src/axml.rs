@@ -0,0 +1,408 @@
+//! Decoder for the binary XML (AXML) files inside an APK, such as AndroidManifest.xml.
+
+pub const ANDROID_NS: &str = "http://schemas.android.com/apk/res/android";
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_NAMESPACE_TYPE: u16 = 0x0100;
+const RES_XML_END_NAMESPACE_TYPE: u16 = 0x0101;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const RES_XML_END_ELEMENT_TYPE: u16 = 0x0103;
+const RES_XML_CDATA_TYPE: u16 = 0x0104;
+const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+
+const UTF8_FLAG: u32 = 0x100;
+
+// Res_value data types
+pub const TYPE_NULL: u8 = 0x00;
+pub const TYPE_REFERENCE: u8 = 0x01;
+pub const TYPE_ATTRIBUTE: u8 = 0x02;
+pub const TYPE_STRING: u8 = 0x03;
+pub const TYPE_FLOAT: u8 = 0x04;
+pub const TYPE_DIMENSION: u8 = 0x05;
+pub const TYPE_FRACTION: u8 = 0x06;
+pub const TYPE_DYNAMIC_REFERENCE: u8 = 0x07;
+pub const TYPE_INT_DEC: u8 = 0x10;
+pub const TYPE_INT_HEX: u8 = 0x11;
+pub const TYPE_INT_BOOLEAN: u8 = 0x12;
+pub const TYPE_INT_COLOR_ARGB8: u8 = 0x1c;
+pub const TYPE_INT_COLOR_RGB4: u8 = 0x1f;
+
+#[derive(Debug, serde::Serialize, Clone, PartialEq)]
+pub struct XmlAttribute {
+    pub namespace: Option<String>,
+    pub name: String,
+    pub resource_id: Option<u32>,
+    pub data_type: u8,
+    pub data: u32,
+    pub value: String,
+}
+
+impl XmlAttribute {
+    /// The referenced resource id for `@0x7f...` values.
+    pub fn reference(&self) -> Option<u32> {
+        match self.data_type {
+            TYPE_REFERENCE | TYPE_DYNAMIC_REFERENCE if self.data != 0 => Some(self.data),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.data_type {
+            TYPE_INT_BOOLEAN => Some(self.data != 0),
+            _ => self.value.parse().ok(),
+        }
+    }
+
+    /// Integer attributes, including flags that are still names in decompiled XML.
+    pub fn as_int(&self) -> Option<u32> {
+        match self.data_type {
+            TYPE_INT_DEC | TYPE_INT_HEX => Some(self.data),
+            _ => self.value.parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, Clone, Default, PartialEq)]
+pub struct XmlElement {
+    pub namespace: Option<String>,
+    pub name: String,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlElement>,
+    pub text: Option<String>,
+}
+
+impl XmlElement {
+    /// Looks up an attribute by name, preferring the android namespace.
+    pub fn attribute(&self, name: &str) -> Option<&XmlAttribute> {
+        self.attributes.iter()
+            .filter(|attribute| attribute.name == name)
+            .max_by_key(|attribute| attribute.namespace.as_deref() == Some(ANDROID_NS))
+    }
+
+    pub fn attribute_value(&self, name: &str) -> Option<&str> {
+        self.attribute(name).map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Renders the element as text XML, with `android:` style prefixes for known namespaces.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        let mut namespaces = Vec::new();
+        self.collect_namespaces(&mut namespaces);
+        self.write_xml(&mut xml, 0, &namespaces);
+        xml
+    }
+
+    fn collect_namespaces(&self, namespaces: &mut Vec<String>) {
+        for namespace in self.attributes.iter().filter_map(|a| a.namespace.as_ref()) {
+            if !namespaces.contains(namespace) {
+                namespaces.push(namespace.clone());
+            }
+        }
+        for child in &self.children {
+            child.collect_namespaces(namespaces);
+        }
+    }
+
+    fn write_xml(&self, xml: &mut String, depth: usize, namespaces: &[String]) {
+        let prefix = |namespace: &str| match namespace {
+            ANDROID_NS => "android".to_string(),
+            "http://schemas.android.com/apk/res-auto" => "app".to_string(),
+            "http://schemas.android.com/tools" => "tools".to_string(),
+            _ => format!("ns{}", namespaces.iter().position(|n| n == namespace).unwrap_or(0)),
+        };
+        let indent = "    ".repeat(depth);
+
+        xml.push_str(&format!("{}<{}", indent, self.name));
+        if depth == 0 {
+            for namespace in namespaces {
+                xml.push_str(&format!(" xmlns:{}=\"{}\"", prefix(namespace), escape(namespace)));
+            }
+        }
+        for attribute in &self.attributes {
+            match &attribute.namespace {
+                Some(namespace) => xml.push_str(&format!(" {}:{}=\"{}\"", prefix(namespace), attribute.name, escape(&attribute.value))),
+                None => xml.push_str(&format!(" {}=\"{}\"", attribute.name, escape(&attribute.value))),
+            }
+        }
+
+        if self.children.is_empty() && self.text.is_none() {
+            xml.push_str("/>\n");
+            return;
+        }
+        xml.push_str(">\n");
+        if let Some(text) = &self.text {
+            xml.push_str(&format!("{}    {}\n", indent, escape(text)));
+        }
+        for child in &self.children {
+            child.write_xml(xml, depth + 1, namespaces);
+        }
+        xml.push_str(&format!("{}</{}>\n", indent, self.name));
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+pub fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// A ResStringPool chunk, shared with the resources.arsc decoder.
+#[derive(Debug, Default, Clone)]
+pub struct StringPool {
+    pub strings: Vec<String>,
+}
+
+impl StringPool {
+    pub fn parse(chunk: &[u8]) -> Option<StringPool> {
+        let header_size = u16_at(chunk, 2)? as usize;
+        let count = u32_at(chunk, 8)? as usize;
+        let flags = u32_at(chunk, 16)?;
+        let strings_start = u32_at(chunk, 20)? as usize;
+        let utf8 = flags & UTF8_FLAG != 0;
+
+        let mut strings = Vec::with_capacity(count.min(chunk.len() / 4));
+        for i in 0..count {
+            let offset = strings_start + u32_at(chunk, header_size + i * 4)? as usize;
+            strings.push(if utf8 { read_utf8(chunk, offset) } else { read_utf16(chunk, offset) }.unwrap_or_default());
+        }
+        Some(StringPool { strings })
+    }
+
+    pub fn get(&self, index: u32) -> Option<&str> {
+        if index == u32::MAX {
+            return None;
+        }
+        self.strings.get(index as usize).map(|s| s.as_str())
+    }
+}
+
+fn read_utf8(data: &[u8], offset: usize) -> Option<String> {
+    // Lengths are 1 or 2 bytes, the high bit marks the 2 byte form. The UTF-16 length comes first and is skipped.
+    let read_length = |offset: usize| -> Option<(usize, usize)> {
+        let first = *data.get(offset)? as usize;
+        if first & 0x80 != 0 {
+            Some((((first & 0x7f) << 8) | *data.get(offset + 1)? as usize, 2))
+        } else {
+            Some((first, 1))
+        }
+    };
+    let (_, skip) = read_length(offset)?;
+    let (length, size) = read_length(offset + skip)?;
+    let start = offset + skip + size;
+    Some(String::from_utf8_lossy(data.get(start..start + length)?).to_string())
+}
+
+fn read_utf16(data: &[u8], offset: usize) -> Option<String> {
+    let first = u16_at(data, offset)? as usize;
+    let (length, start) = if first & 0x8000 != 0 {
+        ((((first & 0x7fff) << 16) | u16_at(data, offset + 2)? as usize), offset + 4)
+    } else {
+        (first, offset + 2)
+    };
+    let bytes = data.get(start..start.checked_add(length.checked_mul(2)?)?)?;
+    let units = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect::<Vec<u16>>();
+    Some(String::from_utf16_lossy(&units))
+}
+
+/// Formats a Res_value the way aapt prints it.
+pub fn format_value(data_type: u8, data: u32, strings: &StringPool) -> String {
+    match data_type {
+        TYPE_NULL => String::new(),
+        TYPE_REFERENCE | TYPE_DYNAMIC_REFERENCE => format!("@0x{:08x}", data),
+        TYPE_ATTRIBUTE => format!("?0x{:08x}", data),
+        TYPE_STRING => strings.get(data).unwrap_or_default().to_string(),
+        TYPE_FLOAT => f32::from_bits(data).to_string(),
+        TYPE_DIMENSION => format!("{}{}", complex_value(data), ["px", "dp", "sp", "pt", "in", "mm"].get((data & 0xf) as usize).unwrap_or(&"")),
+        TYPE_FRACTION => format!("{}{}", complex_value(data) * 100.0, if data & 0xf == 0 { "%" } else { "%p" }),
+        TYPE_INT_DEC => (data as i32).to_string(),
+        TYPE_INT_HEX => format!("0x{:x}", data),
+        TYPE_INT_BOOLEAN => (data != 0).to_string(),
+        TYPE_INT_COLOR_ARGB8..=TYPE_INT_COLOR_RGB4 => format!("#{:08x}", data),
+        _ => format!("0x{:08x}", data),
+    }
+}
+
+/// Decodes the mantissa/radix encoding used by dimensions and fractions.
+fn complex_value(data: u32) -> f32 {
+    const RADIX_MULTS: [f32; 4] = [1.0 / (1 << 8) as f32, 1.0 / (1 << 15) as f32, 1.0 / (1 << 23) as f32, 1.0 / (1u64 << 31) as f32];
+    let mantissa = (data & 0xffffff00) as i32 as f32;
+    mantissa * RADIX_MULTS[((data >> 4) & 0x3) as usize]
+}
+
+/// Framework attribute names, for manifests whose attribute name strings were stripped by obfuscators.
+fn android_attribute_name(id: u32) -> Option<&'static str> {
+    Some(match id {
+        0x01010000 => "theme",
+        0x01010001 => "label",
+        0x01010002 => "icon",
+        0x01010003 => "name",
+        0x01010006 => "permission",
+        0x01010007 => "readPermission",
+        0x01010008 => "writePermission",
+        0x01010009 => "protectionLevel",
+        0x0101000a => "permissionGroup",
+        0x0101000b => "sharedUserId",
+        0x0101000c => "hasCode",
+        0x0101000e => "enabled",
+        0x0101000f => "debuggable",
+        0x01010010 => "exported",
+        0x01010011 => "process",
+        0x01010018 => "authorities",
+        0x0101001b => "grantUriPermissions",
+        0x0101001c => "priority",
+        0x0101001d => "launchMode",
+        0x01010024 => "value",
+        0x01010025 => "resource",
+        0x01010026 => "mimeType",
+        0x01010027 => "scheme",
+        0x01010028 => "host",
+        0x01010029 => "port",
+        0x0101002a => "path",
+        0x0101002b => "pathPrefix",
+        0x0101002c => "pathPattern",
+        0x0101020c => "minSdkVersion",
+        0x0101021b => "versionCode",
+        0x0101021c => "versionName",
+        0x01010270 => "targetSdkVersion",
+        0x01010271 => "maxSdkVersion",
+        0x01010280 => "allowBackup",
+        0x010104ea => "extractNativeLibs",
+        0x010104ec => "usesCleartextTraffic",
+        0x01010527 => "networkSecurityConfig",
+        0x0101052c => "roundIcon",
+        _ => return None,
+    })
+}
+
+/// Builds the same tree from a text XML document, such as the manifest of a decompiled app.
+pub fn parse_text(xml: &str) -> Option<XmlElement> {
+    fn convert(node: roxmltree::Node) -> XmlElement {
+        XmlElement {
+            namespace: node.tag_name().namespace().map(|ns| ns.to_string()),
+            name: node.tag_name().name().to_string(),
+            attributes: node.attributes().map(|attribute| XmlAttribute {
+                namespace: attribute.namespace().map(|ns| ns.to_string()),
+                name: attribute.name().to_string(),
+                resource_id: None,
+                data_type: TYPE_STRING,
+                data: 0,
+                value: attribute.value().to_string(),
+            }).collect(),
+            children: node.children().filter(|child| child.is_element()).map(convert).collect(),
+            text: node.children().find(|child| child.is_text()).and_then(|child| child.text()).map(|text| text.trim().to_string()).filter(|text| !text.is_empty()),
+        }
+    }
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    Some(convert(doc.root_element()))
+}
+
+/// Parses a binary XML document into its root element.
+pub fn parse(data: &[u8]) -> Option<XmlElement> {
+    if u16_at(data, 0)? != RES_XML_TYPE {
+        return None;
+    }
+    let header_size = u16_at(data, 2)? as usize;
+    let total_size = (u32_at(data, 4)? as usize).min(data.len());
+
+    let mut strings = StringPool::default();
+    let mut resource_ids: Vec<u32> = Vec::new();
+    let mut stack: Vec<XmlElement> = Vec::new();
+    let mut root = None;
+    let mut offset = header_size;
+
+    while offset + 8 <= total_size {
+        let chunk_type = u16_at(data, offset)?;
+        let chunk_header_size = u16_at(data, offset + 2)? as usize;
+        let chunk_size = u32_at(data, offset + 4)? as usize;
+        if chunk_size < 8 || offset + chunk_size > total_size {
+            break;
+        }
+        let chunk = &data[offset..offset + chunk_size];
+        // Tree nodes have a 16 byte header (line number and comment), the node data follows it
+        let body = chunk.get(chunk_header_size..).unwrap_or_default();
+
+        match chunk_type {
+            RES_STRING_POOL_TYPE => strings = StringPool::parse(chunk)?,
+            RES_XML_RESOURCE_MAP_TYPE => {
+                resource_ids = body.chunks_exact(4).map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]])).collect();
+            }
+            RES_XML_START_ELEMENT_TYPE => {
+                let namespace = strings.get(u32_at(body, 0)?).map(|s| s.to_string());
+                let name = strings.get(u32_at(body, 4)?).unwrap_or_default().to_string();
+                let attribute_start = u16_at(body, 8)? as usize;
+                let attribute_size = u16_at(body, 10)? as usize;
+                let attribute_count = u16_at(body, 12)? as usize;
+
+                let mut attributes = Vec::with_capacity(attribute_count);
+                for i in 0..attribute_count {
+                    let attribute = body.get(attribute_start + i * attribute_size..)?;
+                    let name_index = u32_at(attribute, 4)?;
+                    let raw_value = u32_at(attribute, 8)?;
+                    let data_type = *attribute.get(15)?;
+                    let value_data = u32_at(attribute, 16)?;
+                    let resource_id = resource_ids.get(name_index as usize).copied();
+
+                    let mut attribute_name = strings.get(name_index).unwrap_or_default().to_string();
+                    if attribute_name.is_empty() {
+                        attribute_name = resource_id.and_then(android_attribute_name).map(|n| n.to_string()).unwrap_or(format!("0x{:08x}", resource_id.unwrap_or(name_index)));
+                    }
+                    let value = match strings.get(raw_value) {
+                        Some(raw) if data_type == TYPE_STRING || data_type == TYPE_NULL => raw.to_string(),
+                        _ => format_value(data_type, value_data, &strings),
+                    };
+
+                    attributes.push(XmlAttribute {
+                        namespace: strings.get(u32_at(attribute, 0)?).map(|s| s.to_string()),
+                        name: attribute_name,
+                        resource_id,
+                        data_type,
+                        data: value_data,
+                        value,
+                    });
+                }
+
+                stack.push(XmlElement { namespace, name, attributes, ..Default::default() });
+            }
+            RES_XML_END_ELEMENT_TYPE => {
+                let element = stack.pop()?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            RES_XML_CDATA_TYPE => {
+                if let (Some(element), Some(text)) = (stack.last_mut(), strings.get(u32_at(body, 0)?)) {
+                    element.text = Some(text.to_string());
+                }
+            }
+            RES_XML_START_NAMESPACE_TYPE | RES_XML_END_NAMESPACE_TYPE => {}
+            _ => {}
+        }
+
+        offset += chunk_size;
+    }
+
+    // Truncated documents still yield what was decoded so far
+    while let Some(element) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(element),
+            None => root = Some(element),
+        }
+    }
+    root
+}
@@ -0,0 +1,231 @@
+use std::{fs::File, io::{Read, Write}, path::Path, process::Stdio, time::{SystemTime, UNIX_EPOCH}};
+
+use tauri::{AppHandle, Emitter};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::utils::{adb_command, as_root, shell_quote, user_args};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupMethod {
+    AdbBackup,
+    RunAs,
+    Root,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct BackupMetadata {
+    pub package: String,
+    pub version_name: Option<String>,
+    pub version_code: Option<String>,
+    pub device_id: String,
+    pub device_model: String,
+    pub user_id: u32,
+    pub method: BackupMethod,
+    pub created_at: u64,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BackupResult {
+    pub package: String,
+    pub path: Option<String>,
+    pub metadata: Option<BackupMetadata>,
+    pub error: Option<String>,
+}
+
+fn adb_shell(device_id: &str, command: &str) -> String {
+    adb_command(device_id)
+        .args(["shell", command])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn root_command(device_id: &str, command: &str) -> Option<String> {
+    as_root(command, |command| Ok(adb_shell(device_id, command))).ok().flatten()
+}
+
+fn data_dir(package: &str, user_id: u32) -> String {
+    format!("/data/user/{}/{}", user_id, package)
+}
+
+fn run_as(package: &str, user_id: u32, command: &str) -> String {
+    format!("run-as {} {} {}", user_args(Some(user_id).filter(|id| *id != 0)).join(" "), shell_quote(package), command)
+}
+
+/// Picks the first method that works: run-as for debuggable apps, root, then the deprecated adb backup.
+fn detect_method(device_id: &str, package: &str, user_id: u32) -> BackupMethod {
+    if adb_shell(device_id, &format!("{} id -u 2>/dev/null", run_as(package, user_id, ""))).parse::<u32>().is_ok() {
+        BackupMethod::RunAs
+    } else if root_command(device_id, "true").is_some() {
+        BackupMethod::Root
+    } else {
+        BackupMethod::AdbBackup
+    }
+}
+
+fn backup_blocking(device_id: &str, package: &str, user_id: u32, method: Option<BackupMethod>, dest: &str) -> Result<(String, BackupMetadata), String> {
+    let method = method.unwrap_or_else(|| detect_method(device_id, package, user_id));
+    let dumpsys = adb_shell(device_id, &format!("dumpsys package {}", shell_quote(package)));
+    let field = |name: &str| dumpsys.split_whitespace().find_map(|part| part.strip_prefix(name)).map(|v| v.to_string());
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let metadata = BackupMetadata {
+        package: package.to_string(),
+        version_name: field("versionName="),
+        version_code: field("versionCode="),
+        device_id: device_id.to_string(),
+        device_model: adb_shell(device_id, "getprop ro.product.model"),
+        user_id,
+        method,
+        created_at,
+    };
+
+    let data_name = match method {
+        BackupMethod::AdbBackup => "backup.ab",
+        _ => "data.tar",
+    };
+    let data_path = std::env::temp_dir().join(format!("tuyu-{}-{}-{}", package, created_at, data_name));
+
+    match method {
+        BackupMethod::AdbBackup => {
+            // The user has to confirm the backup on the device, adb returns once it's done
+            let output = adb_command(device_id)
+                .args(["backup", "-f", &data_path.to_string_lossy(), "-noapk", package])
+                .output()
+                .map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+            }
+        }
+        BackupMethod::RunAs | BackupMethod::Root => {
+            let command = match method {
+                BackupMethod::RunAs => run_as(package, user_id, "tar -cf - ."),
+                _ => root_command(device_id, &format!("tar -cf - -C {} .", shell_quote(&data_dir(package, user_id)))).ok_or("Device is not rooted")?,
+            };
+            // exec-out keeps the tar stream binary safe
+            let file = File::create(&data_path).map_err(|e| e.to_string())?;
+            let status = adb_command(device_id)
+                .args(["exec-out", &command])
+                .stdout(Stdio::from(file))
+                .stderr(Stdio::null())
+                .status()
+                .map_err(|e| e.to_string())?;
+            if !status.success() {
+                let _ = std::fs::remove_file(&data_path);
+                return Err(format!("tar exited with {}", status));
+            }
+        }
+    }
+
+    let size = std::fs::metadata(&data_path).map(|m| m.len()).unwrap_or(0);
+    if size == 0 {
+        let _ = std::fs::remove_file(&data_path);
+        return Err("Backup is empty, the app may not allow backups".to_string());
+    }
+
+    let archive_path = Path::new(dest).join(format!("{}_{}.tuyu-backup.zip", package, created_at));
+    let result = write_archive(&archive_path, &metadata, data_name, &data_path);
+    let _ = std::fs::remove_file(&data_path);
+    result?;
+
+    Ok((archive_path.to_string_lossy().to_string(), metadata))
+}
+
+fn write_archive(archive_path: &Path, metadata: &BackupMetadata, data_name: &str, data_path: &Path) -> Result<(), String> {
+    let file = File::create(archive_path).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().large_file(true);
+
+    zip.start_file("metadata.json", options).map_err(|e| e.to_string())?;
+    zip.write_all(serde_json::to_string_pretty(metadata).map_err(|e| e.to_string())?.as_bytes()).map_err(|e| e.to_string())?;
+
+    zip.start_file(data_name, options).map_err(|e| e.to_string())?;
+    let mut data = File::open(data_path).map_err(|e| e.to_string())?;
+    std::io::copy(&mut data, &mut zip).map_err(|e| e.to_string())?;
+
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn read_metadata(archive_path: &str) -> Result<BackupMetadata, String> {
+    let file = File::open(archive_path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let mut metadata = String::new();
+    archive.by_name("metadata.json").map_err(|e| e.to_string())?.read_to_string(&mut metadata).map_err(|e| e.to_string())?;
+    serde_json::from_str(&metadata).map_err(|e| e.to_string())
+}
+
+fn restore_blocking(device_id: &str, archive_path: &str, user_id: Option<u32>) -> Result<BackupMetadata, String> {
+    let metadata = read_metadata(archive_path)?;
+    let user_id = user_id.unwrap_or(metadata.user_id);
+    let package = metadata.package.as_str();
+
+    let file = File::open(archive_path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let data_name = if metadata.method == BackupMethod::AdbBackup { "backup.ab" } else { "data.tar" };
+    let data_path = std::env::temp_dir().join(format!("tuyu-restore-{}-{}", metadata.created_at, data_name));
+    // Every exit goes through the cleanup below, so nothing is left behind on either side
+    let result = (|| {
+        let mut entry = archive.by_name(data_name).map_err(|e| e.to_string())?;
+        let mut data = File::create(&data_path).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut data).map_err(|e| e.to_string())?;
+        drop(data);
+
+        match metadata.method {
+            BackupMethod::AdbBackup => {
+                let output = adb_command(device_id).args(["restore", &data_path.to_string_lossy()]).output().map_err(|e| e.to_string())?;
+                if output.status.success() { Ok(()) } else { Err(String::from_utf8_lossy(&output.stderr).trim().to_string()) }
+            }
+            BackupMethod::RunAs | BackupMethod::Root => {
+                let remote_tar = "/data/local/tmp/tuyu_restore.tar";
+                let result = (|| {
+                    let output = adb_command(device_id).args(["push", &data_path.to_string_lossy(), remote_tar]).output().map_err(|e| e.to_string())?;
+                    if !output.status.success() {
+                        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+                    }
+
+                    // The app must not be running while its files are replaced
+                    adb_shell(device_id, &format!("am force-stop {}", shell_quote(package)));
+                    let restore = match detect_method(device_id, package, user_id) {
+                        BackupMethod::RunAs => Some(format!("cat {} | {}", remote_tar, run_as(package, user_id, "tar -xf -"))),
+                        _ => {
+                            let dir = shell_quote(&data_dir(package, user_id));
+                            // Files have to belong to the app uid on this device, which differs between devices
+                            root_command(device_id, &format!(
+                                "tar -xf {remote_tar} -C {dir} && owner=$(stat -c %u:%g {dir}) && chown -R $owner {dir} && restorecon -R {dir}"
+                            ))
+                        }
+                    };
+                    let restore = restore.ok_or("Restoring a tar backup needs a debuggable app or a rooted device")?;
+                    let output = adb_shell(device_id, &format!("({}) 2>&1; echo exit=$?", restore));
+                    if output.ends_with("exit=0") { Ok(()) } else { Err(output) }
+                })();
+                adb_shell(device_id, &format!("rm -f {}", remote_tar));
+                result
+            }
+        }
+    })();
+    let _ = std::fs::remove_file(&data_path);
+    result.map(|_| metadata)
+}
+
+pub fn backup(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>, method: Option<BackupMethod>, dest: String) {
+    std::thread::spawn(move || {
+        let result = match backup_blocking(&device_id, &package, user_id.unwrap_or(0), method, &dest) {
+            Ok((path, metadata)) => BackupResult { package, path: Some(path), metadata: Some(metadata), error: None },
+            Err(error) => BackupResult { package, path: None, metadata: None, error: Some(error) },
+        };
+        handle.emit("backup-finished", result).unwrap();
+    });
+}
+
+pub fn restore(handle: AppHandle, device_id: String, archive_path: String, user_id: Option<u32>) {
+    std::thread::spawn(move || {
+        let result = match restore_blocking(&device_id, &archive_path, user_id) {
+            Ok(metadata) => BackupResult { package: metadata.package.clone(), path: Some(archive_path), metadata: Some(metadata), error: None },
+            Err(error) => BackupResult { package: String::new(), path: Some(archive_path), metadata: None, error: Some(error) },
+        };
+        handle.emit("restore-finished", result).unwrap();
+    });
+}
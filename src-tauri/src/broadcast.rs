@@ -0,0 +1,131 @@
+use std::{fs::File, thread, time::Instant};
+
+use adb_client::{ADBDeviceExt, ADBServerDevice, RebootType};
+use tauri::{AppHandle, Emitter};
+
+use crate::utils::{check_shell_output, install_staged_apk, shell_quote, user_args, SettingsNamespace};
+
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BroadcastOperation {
+    Shell { command: String },
+    Install { apk_path: String, user_id: Option<u32> },
+    Uninstall { package: String, user_id: Option<u32> },
+    Push { local_path: String, remote_path: String },
+    Reboot { mode: Option<String> },
+    Setting { namespace: SettingsNamespace, key: String, value: String },
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BroadcastProgress {
+    pub broadcast_id: String,
+    pub device_id: String,
+    pub status: String, // running, success, failed
+    pub output: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BroadcastResult {
+    pub device_id: String,
+    pub success: bool,
+    pub output: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BroadcastSummary {
+    pub broadcast_id: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BroadcastResult>,
+}
+
+fn shell(device: &mut ADBServerDevice, command: &str) -> Result<String, String> {
+    let mut output = Vec::new();
+    device.shell_command(&[command], &mut output).map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+fn execute(device: &mut ADBServerDevice, operation: &BroadcastOperation) -> Result<String, String> {
+    match operation {
+        BroadcastOperation::Shell { command } => shell(device, command),
+        BroadcastOperation::Install { apk_path, user_id: None } => device.install(apk_path).map(|_| "Success".to_string()).map_err(|e| e.to_string()),
+        BroadcastOperation::Install { apk_path, user_id } => install_staged_apk(device, apk_path, *user_id),
+        BroadcastOperation::Uninstall { package, user_id } => {
+            let output = shell(device, &format!("pm uninstall {} {} 2>&1", user_args(*user_id).join(" "), shell_quote(package)))?;
+            if output.trim() == "Success" { Ok(output) } else { Err(output.trim().to_string()) }
+        }
+        BroadcastOperation::Push { local_path, remote_path } => {
+            let mut file = File::open(local_path).map_err(|e| e.to_string())?;
+            device.push(&mut file, remote_path).map(|_| format!("{} pushed", remote_path)).map_err(|e| e.to_string())
+        }
+        BroadcastOperation::Reboot { mode } => {
+            let reboot_type = match mode.as_deref() {
+                Some("bootloader") => RebootType::Bootloader,
+                Some("recovery") => RebootType::Recovery,
+                Some("sideload") => RebootType::Sideload,
+                _ => RebootType::System,
+            };
+            device.reboot(reboot_type).map(|_| "Rebooting".to_string()).map_err(|e| e.to_string())
+        }
+        BroadcastOperation::Setting { namespace, key, value } => {
+            check_shell_output(shell(device, &format!("settings put {} {} {} 2>&1", namespace.as_str(), shell_quote(key), shell_quote(value)))?)
+        }
+    }
+}
+
+/// Runs the operation on every device concurrently, emitting `broadcast-progress` per device and `broadcast-finished` once all are done.
+pub fn run(handle: AppHandle, broadcast_id: String, devices: Vec<(String, Result<ADBServerDevice, String>)>, operation: BroadcastOperation) {
+    thread::spawn(move || {
+        let workers = devices.into_iter().map(|(device_id, device)| {
+            let worker_device_id = device_id.clone();
+            let handle = handle.clone();
+            let broadcast_id = broadcast_id.clone();
+            let operation = operation.clone();
+
+            let worker = thread::spawn(move || {
+                let progress = |status: &str, output: Option<String>| BroadcastProgress {
+                    broadcast_id: broadcast_id.clone(),
+                    device_id: device_id.clone(),
+                    status: status.to_string(),
+                    output,
+                };
+                handle.emit("broadcast-progress", progress("running", None)).unwrap();
+
+                let started = Instant::now();
+                let result = device.and_then(|mut device| execute(&mut device, &operation));
+                let (success, output) = match result {
+                    Ok(output) => (true, output),
+                    Err(error) => (false, error),
+                };
+                handle.emit("broadcast-progress", progress(if success { "success" } else { "failed" }, Some(output.clone()))).unwrap();
+
+                BroadcastResult {
+                    device_id: device_id.clone(),
+                    success,
+                    output,
+                    duration_ms: started.elapsed().as_millis() as u64,
+                }
+            });
+            (worker_device_id, Instant::now(), worker)
+        }).collect::<Vec<_>>();
+
+        // A panicked worker still counts as a failure for its device
+        let results = workers.into_iter().map(|(device_id, started, worker)| {
+            worker.join().unwrap_or_else(|_| {
+                let output = "Operation panicked".to_string();
+                let progress = BroadcastProgress { broadcast_id: broadcast_id.clone(), device_id: device_id.clone(), status: "failed".to_string(), output: Some(output.clone()) };
+                handle.emit("broadcast-progress", progress).unwrap();
+                BroadcastResult { device_id, success: false, output, duration_ms: started.elapsed().as_millis() as u64 }
+            })
+        }).collect::<Vec<BroadcastResult>>();
+        let succeeded = results.iter().filter(|result| result.success).count();
+        let summary = BroadcastSummary {
+            broadcast_id,
+            succeeded,
+            failed: results.len() - succeeded,
+            results,
+        };
+        handle.emit("broadcast-finished", summary).unwrap();
+    });
+}
@@ -0,0 +1,233 @@
+use std::{fs::File, io::{BufRead, BufReader, Read}, path::Path, process::Stdio};
+
+use tauri::{AppHandle, Emitter};
+use zip::ZipArchive;
+
+use crate::utils::adb_command;
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BugreportProgress {
+    pub device_id: String,
+    pub stage: String, // begin, progress, pulling, indexing
+    pub progress: u32,
+    pub max: u32,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BugreportEntry {
+    pub name: String,
+    pub size: u64,
+    pub kind: String, // dumpstate, anr, tombstone, proto, other
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BugreportSection {
+    pub title: String,
+    pub command: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, serde::Serialize, Clone, Default)]
+pub struct BugreportIndex {
+    pub path: String,
+    pub main_entry: Option<String>,
+    pub entries: Vec<BugreportEntry>,
+    pub sections: Vec<BugreportSection>,
+    pub anr_traces: Vec<String>,
+    pub tombstones: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct BugreportResult {
+    pub device_id: String,
+    pub index: Option<BugreportIndex>,
+    pub error: Option<String>,
+}
+
+pub fn capture(handle: AppHandle, device_id: String, dest: String) {
+    std::thread::spawn(move || {
+        let result = match capture_blocking(&handle, &device_id, &dest) {
+            Ok(index) => BugreportResult { device_id, index: Some(index), error: None },
+            Err(error) => BugreportResult { device_id, index: None, error: Some(error) },
+        };
+        handle.emit("bugreport-finished", result).unwrap();
+    });
+}
+
+fn emit_progress(handle: &AppHandle, device_id: &str, stage: &str, progress: u32, max: u32) {
+    let progress = BugreportProgress {
+        device_id: device_id.to_string(),
+        stage: stage.to_string(),
+        progress,
+        max,
+    };
+    handle.emit("bugreport-progress", progress).unwrap();
+}
+
+fn capture_blocking(handle: &AppHandle, device_id: &str, dest: &str) -> Result<BugreportIndex, String> {
+    let mut child = adb_command(device_id)
+        .args(["shell", "bugreportz", "-p"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start bugreportz: {}", e))?;
+
+    let stdout = child.stdout.take().expect("Failed to get stdout");
+    let mut remote_path = None;
+    let mut error = None;
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        let line = line.trim();
+        if let Some(path) = line.strip_prefix("BEGIN:") {
+            emit_progress(handle, device_id, "begin", 0, 100);
+            remote_path = Some(path.to_string());
+        } else if let Some(progress) = line.strip_prefix("PROGRESS:") {
+            // Reported as current/max, max grows while dumpstate discovers more work
+            let (current, max) = progress.split_once('/').unwrap_or((progress, "100"));
+            emit_progress(handle, device_id, "progress", current.parse().unwrap_or(0), max.parse().unwrap_or(100));
+        } else if let Some(path) = line.strip_prefix("OK:") {
+            remote_path = Some(path.to_string());
+        } else if let Some(reason) = line.strip_prefix("FAIL:") {
+            error = Some(reason.to_string());
+        }
+    }
+    let _ = child.wait();
+
+    if let Some(error) = error {
+        return Err(error);
+    }
+    let remote_path = remote_path.ok_or("bugreportz did not report an output file")?;
+
+    emit_progress(handle, device_id, "pulling", 0, 0);
+    let dest_path = Path::new(dest);
+    let local_path = if dest_path.is_dir() {
+        let file_name = Path::new(&remote_path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or("bugreport.zip".to_string());
+        dest_path.join(file_name)
+    } else {
+        dest_path.to_path_buf()
+    };
+    let output = adb_command(device_id)
+        .args(["pull", &remote_path, &local_path.to_string_lossy()])
+        .output()
+        .map_err(|e| format!("Failed to pull bugreport: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    emit_progress(handle, device_id, "indexing", 0, 0);
+    index(&local_path.to_string_lossy()).ok_or("Failed to index bugreport".to_string())
+}
+
+fn entry_kind(name: &str) -> &'static str {
+    if name.starts_with("FS/data/anr/") {
+        "anr"
+    } else if name.starts_with("FS/data/tombstones/") {
+        "tombstone"
+    } else if name.starts_with("proto/") || name.ends_with(".proto") {
+        "proto"
+    } else if (name.starts_with("bugreport-") && name.ends_with(".txt")) || name.starts_with("dumpstate") {
+        "dumpstate"
+    } else {
+        "other"
+    }
+}
+
+/// Parses the `------ TITLE (command) ------` section headers of the main dumpstate file.
+fn parse_sections(content: &str) -> Vec<BugreportSection> {
+    let mut sections: Vec<BugreportSection> = Vec::new();
+    let mut line_count = 0;
+
+    for (number, line) in content.lines().enumerate() {
+        line_count = number + 1;
+        let Some(header) = line.strip_prefix("------ ").and_then(|l| l.strip_suffix(" ------")) else { continue };
+        // Footers look like `------ 0.012s was the duration of 'TITLE' ------`
+        if header.contains("was the duration of") {
+            continue;
+        }
+        if let Some(last) = sections.last_mut() {
+            last.end_line = number;
+        }
+
+        let (title, command) = match header.rfind(" (") {
+            Some(start) if header.ends_with(')') => (&header[..start], Some(header[start + 2..header.len() - 1].to_string())),
+            _ => (header, None),
+        };
+        sections.push(BugreportSection {
+            title: title.to_string(),
+            command,
+            start_line: number + 1,
+            end_line: number + 1,
+        });
+    }
+    if let Some(last) = sections.last_mut() {
+        last.end_line = line_count;
+    }
+
+    sections
+}
+
+pub fn index(path: &str) -> Option<BugreportIndex> {
+    let file = File::open(path).ok()?;
+    let mut archive = ZipArchive::new(file).ok()?;
+    let mut index = BugreportIndex {
+        path: path.to_string(),
+        ..Default::default()
+    };
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).ok()?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let kind = entry_kind(&name);
+        match kind {
+            "anr" => index.anr_traces.push(name.clone()),
+            "tombstone" => index.tombstones.push(name.clone()),
+            _ => {}
+        }
+        index.entries.push(BugreportEntry { name, size: entry.size(), kind: kind.to_string() });
+    }
+
+    // bugreportz names the dumpstate file in main_entry.txt, older archives only have bugreport-*.txt
+    let main_entry = match archive.by_name("main_entry.txt") {
+        Ok(mut entry) => {
+            let mut name = String::new();
+            entry.read_to_string(&mut name).ok()?;
+            Some(name.trim().to_string())
+        }
+        Err(_) => index.entries.iter().find(|e| e.name.starts_with("bugreport-") && e.name.ends_with(".txt")).map(|e| e.name.clone()),
+    };
+
+    if let Some(main_entry) = &main_entry {
+        if let Some(content) = read_entry(&mut archive, main_entry) {
+            index.sections = parse_sections(&content);
+        }
+    }
+    index.main_entry = main_entry;
+
+    Some(index)
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).ok()?;
+    Some(String::from_utf8_lossy(&data).to_string())
+}
+
+/// Reads an entry of a pulled bugreport, optionally limited to a line range from a section.
+pub fn read(path: &str, name: &str, start_line: Option<usize>, end_line: Option<usize>) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut archive = ZipArchive::new(file).ok()?;
+    let content = read_entry(&mut archive, name)?;
+
+    match (start_line, end_line) {
+        (None, None) => Some(content),
+        (start, end) => {
+            let start = start.unwrap_or(1).max(1);
+            let end = end.unwrap_or(usize::MAX);
+            Some(content.lines().skip(start - 1).take(end.saturating_sub(start) + 1).collect::<Vec<&str>>().join("\n"))
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{fs::File, io::{Read, Write}, process::{Child, Stdio}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, thread, time::{Duration, Instant}};
+
+use tauri::{AppHandle, Emitter};
+
+use crate::utils::adb_command;
+
+pub const REMOTE_TCPDUMP: &str = "/data/local/tmp/tcpdump";
+pub const REMOTE_TCPDUMP_PID: &str = "/data/local/tmp/tuyu_tcpdump.pid";
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct CaptureProgress {
+    pub device_id: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub running: bool,
+}
+
+pub struct PacketCapture {
+    pub child: Child,
+    pub pid: u32, // tcpdump on the device, the only process stop signals
+    pub packets: Arc<AtomicU64>,
+    pub bytes: Arc<AtomicU64>,
+    pub running: Arc<AtomicBool>,
+}
+
+/// Counts packets in a pcap stream by walking the record headers as data arrives.
+#[derive(Default)]
+struct PcapCounter {
+    global_header: Vec<u8>,
+    record_header: Vec<u8>,
+    skip: usize,
+    packets: u64,
+}
+
+impl PcapCounter {
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.global_header.len() < 24 {
+                let take = (24 - self.global_header.len()).min(data.len());
+                self.global_header.extend_from_slice(&data[..take]);
+                data = &data[take..];
+            } else if self.skip > 0 {
+                let take = self.skip.min(data.len());
+                self.skip -= take;
+                data = &data[take..];
+            } else {
+                let take = (16 - self.record_header.len()).min(data.len());
+                self.record_header.extend_from_slice(&data[..take]);
+                data = &data[take..];
+                if self.record_header.len() == 16 {
+                    let included = [self.record_header[8], self.record_header[9], self.record_header[10], self.record_header[11]];
+                    // The magic number is written in the capturing host's byte order
+                    let little_endian = self.global_header[..4] == [0xd4, 0xc3, 0xb2, 0xa1] || self.global_header[..4] == [0x4d, 0x3c, 0xb2, 0xa1];
+                    self.skip = if little_endian { u32::from_le_bytes(included) } else { u32::from_be_bytes(included) } as usize;
+                    self.record_header.clear();
+                    self.packets += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The wrapped tcpdump command writes its pid to `REMOTE_TCPDUMP_PID`, `read_pid` returns it once it's there.
+pub fn start(handle: AppHandle, device_id: String, command: String, path: String, read_pid: impl Fn() -> Option<u32>) -> Result<PacketCapture, String> {
+    let mut file = File::create(&path).map_err(|e| e.to_string())?;
+    // exec-out keeps the pcap stream binary safe
+    let mut child = adb_command(&device_id)
+        .args(["exec-out", &command])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start tcpdump: {}", e))?;
+
+    let mut stdout = child.stdout.take().expect("Failed to get stdout");
+    let packets = Arc::new(AtomicU64::new(0));
+    let bytes = Arc::new(AtomicU64::new(0));
+    let running = Arc::new(AtomicBool::new(true));
+
+    let packets_clone = packets.clone();
+    let bytes_clone = bytes.clone();
+    let running_clone = running.clone();
+    thread::spawn(move || {
+        let mut counter = PcapCounter::default();
+        let mut buf = [0u8; 64 * 1024];
+        let mut last_emit = Instant::now();
+
+        loop {
+            let read = match stdout.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            if file.write_all(&buf[..read]).is_err() {
+                break;
+            }
+            counter.feed(&buf[..read]);
+            packets_clone.store(counter.packets, Ordering::Relaxed);
+            bytes_clone.fetch_add(read as u64, Ordering::Relaxed);
+
+            if last_emit.elapsed() >= Duration::from_millis(500) {
+                last_emit = Instant::now();
+                let progress = CaptureProgress {
+                    device_id: device_id.clone(),
+                    packets: counter.packets,
+                    bytes: bytes_clone.load(Ordering::Relaxed),
+                    running: true,
+                };
+                handle.emit("packet-capture-progress", progress).unwrap();
+            }
+        }
+
+        let _ = file.flush();
+        running_clone.store(false, Ordering::Relaxed);
+        let progress = CaptureProgress {
+            device_id,
+            packets: counter.packets,
+            bytes: bytes_clone.load(Ordering::Relaxed),
+            running: false,
+        };
+        handle.emit("packet-capture-progress", progress).unwrap();
+    });
+
+    let Some(pid) = (0..20).find_map(|_| read_pid().or_else(|| {
+        thread::sleep(Duration::from_millis(100));
+        None
+    })) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err("tcpdump exited before it started capturing".to_string());
+    };
+
+    Ok(PacketCapture { child, pid, packets, bytes, running })
+}
@@ -0,0 +1,86 @@
+use base64::{engine::general_purpose, Engine};
+use md5::{Digest, Md5};
+
+/// A single DER tag-length-value, `raw` includes the header.
+#[derive(Debug, Clone, Copy)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+pub fn read_tlv(data: &[u8]) -> Option<(Tlv<'_>, &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (length, header) = if first & 0x80 == 0 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = data.get(2..2 + count)?.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + count)
+    };
+
+    let end = header.checked_add(length)?;
+    let tlv = Tlv {
+        tag,
+        content: data.get(header..end)?,
+        raw: &data[..end],
+    };
+    Some((tlv, &data[end..]))
+}
+
+/// Splits the content of a constructed value into its children.
+pub fn children(mut data: &[u8]) -> Vec<Tlv<'_>> {
+    let mut children = Vec::new();
+    while let Some((tlv, rest)) = read_tlv(data) {
+        children.push(tlv);
+        data = rest;
+    }
+    children
+}
+
+/// Accepts a PEM or DER encoded certificate and returns the DER bytes.
+pub fn load_certificate(data: &[u8]) -> Option<Vec<u8>> {
+    if data.first() == Some(&0x30) {
+        return Some(data.to_vec());
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let start = text.find("-----BEGIN CERTIFICATE-----")? + "-----BEGIN CERTIFICATE-----".len();
+    let end = start + text[start..].find("-----END CERTIFICATE-----")?;
+    let body = text[start..end].chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    general_purpose::STANDARD.decode(body).ok()
+}
+
+pub fn to_pem(der: &[u8]) -> String {
+    let encoded = general_purpose::STANDARD.encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for chunk in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(chunk));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Returns the fields of the TBSCertificate, with the optional `[0]` version removed.
+pub fn tbs_fields(der: &[u8]) -> Option<Vec<Tlv<'_>>> {
+    let (certificate, _) = read_tlv(der)?;
+    let (tbs, _) = read_tlv(certificate.content)?;
+    let mut fields = children(tbs.content);
+    if fields.first().is_some_and(|field| field.tag == 0xa0) {
+        fields.remove(0);
+    }
+    Some(fields)
+}
+
+/// The `openssl x509 -subject_hash_old` value Android uses to name files in the CA stores.
+pub fn subject_hash_old(der: &[u8]) -> Option<String> {
+    // serialNumber, signature, issuer, validity, subject
+    let subject = *tbs_fields(der)?.get(4)?;
+    let digest = Md5::digest(subject.raw);
+    Some(format!("{:08x}", u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])))
+}
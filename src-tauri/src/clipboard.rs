@@ -0,0 +1,292 @@
+use std::{io::{Read, Write}, net::TcpStream, process::{Child, Stdio}, sync::{atomic::{AtomicBool, Ordering}, Arc}, thread, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use adb_client::{ADBDeviceExt, ADBServerDevice};
+use tauri::{AppHandle, Emitter};
+
+use crate::utils::adb_command;
+
+pub const SCRCPY_VERSION: &str = "3.1";
+pub const REMOTE_SCRCPY_SERVER: &str = "/data/local/tmp/scrcpy-server.jar";
+
+// scrcpy control protocol message types
+const TYPE_GET_CLIPBOARD: u8 = 8;
+const TYPE_SET_CLIPBOARD: u8 = 9;
+const DEVICE_MSG_TYPE_CLIPBOARD: u8 = 0;
+const DEVICE_MSG_TYPE_ACK_CLIPBOARD: u8 = 1;
+const DEVICE_MSG_TYPE_UHID_OUTPUT: u8 = 2;
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct ClipboardEvent {
+    pub device_id: String,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CmdClipboardError {
+    /// `cmd` or its clipboard service is missing, scrcpy-server is used instead
+    Unavailable,
+    Failed(String),
+}
+
+fn check_cmd_output(output: &str) -> Result<(), CmdClipboardError> {
+    const UNAVAILABLE: [&str; 5] = ["Unknown command", "Can't find service", "No shell command implementation", "cmd: not found", "cmd: inaccessible or not found"];
+    if UNAVAILABLE.iter().any(|message| output.contains(message)) {
+        return Err(CmdClipboardError::Unavailable);
+    }
+    // e.g. a SecurityException when the shell isn't allowed to touch the clipboard
+    if output.starts_with("Exception occurred") || output.lines().next().is_some_and(|line| line.starts_with("java.") || line.starts_with("Error:")) {
+        return Err(CmdClipboardError::Failed(output.to_string()));
+    }
+    Ok(())
+}
+
+/// Parses `cmd clipboard get-primary-clip 2>&1`.
+pub fn parse_cmd_clipboard(output: &str) -> Result<Option<String>, CmdClipboardError> {
+    let output = output.trim_end_matches(['\r', '\n']);
+    check_cmd_output(output)?;
+    if output.is_empty() || output == "null" {
+        return Ok(None);
+    }
+
+    // ClipData is printed as `ClipData { text/plain {T:the text} }`
+    match (output.find("{T:"), output.rfind("} }")) {
+        (Some(start), Some(end)) if end > start => Ok(Some(output[start + 3..end].to_string())),
+        _ => Ok(Some(output.to_string())),
+    }
+}
+
+/// Checks `cmd clipboard set-primary-clip 2>&1`, which prints nothing when the clip was set.
+pub fn check_cmd_clipboard_set(output: &str) -> Result<(), CmdClipboardError> {
+    let output = output.trim();
+    check_cmd_output(output)?;
+    if output.is_empty() {
+        Ok(())
+    } else {
+        Err(CmdClipboardError::Failed(output.to_string()))
+    }
+}
+
+/// A control-only scrcpy-server session, used for the clipboard on devices without `cmd clipboard`.
+pub struct ScrcpyControl {
+    device_id: String,
+    port: String,
+    child: Child,
+    stream: TcpStream,
+    sequence: u64,
+}
+
+impl ScrcpyControl {
+    /// Starts the server, the scrcpy-server.jar must already be pushed to `REMOTE_SCRCPY_SERVER`.
+    pub fn connect(device_id: &str, autosync: bool) -> Result<ScrcpyControl, String> {
+        let scid = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos() & 0x7fffffff;
+        let output = adb_command(device_id)
+            .args(["forward", "tcp:0", &format!("localabstract:scrcpy_{:08x}", scid)])
+            .output()
+            .map_err(|e| e.to_string())?;
+        let port = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || port.is_empty() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+
+        let mut child = adb_command(device_id)
+            .args([
+                "shell",
+                &format!("CLASSPATH={}", REMOTE_SCRCPY_SERVER),
+                "app_process",
+                "/",
+                "com.genymobile.scrcpy.Server",
+                SCRCPY_VERSION,
+                &format!("scid={:08x}", scid),
+                "log_level=warn",
+                "video=false",
+                "audio=false",
+                "control=true",
+                "tunnel_forward=true",
+                "send_device_meta=false",
+                "cleanup=false",
+                &format!("clipboard_autosync={}", autosync),
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+
+        // adb accepts the connection before the server listens, the dummy byte tells us it's really up
+        for _ in 0..50 {
+            thread::sleep(Duration::from_millis(100));
+            let Ok(mut stream) = TcpStream::connect(format!("127.0.0.1:{}", port)) else { continue };
+            let mut dummy = [0u8; 1];
+            if stream.read_exact(&mut dummy).is_ok() {
+                return Ok(ScrcpyControl {
+                    device_id: device_id.to_string(),
+                    port,
+                    child,
+                    stream,
+                    sequence: 0,
+                });
+            }
+        }
+
+        let _ = child.kill();
+        let _ = adb_command(device_id).args(["forward", "--remove", &format!("tcp:{}", port)]).output();
+        Err("scrcpy-server did not start".to_string())
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        let _ = self.stream.set_read_timeout(timeout);
+    }
+
+    pub fn set_clipboard(&mut self, text: &str) -> Result<(), String> {
+        self.sequence += 1;
+        let mut message = vec![TYPE_SET_CLIPBOARD];
+        message.extend_from_slice(&self.sequence.to_be_bytes());
+        message.push(0); // don't paste
+        message.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        message.extend_from_slice(text.as_bytes());
+        self.stream.write_all(&message).map_err(|e| e.to_string())?;
+
+        // Wait for the ack so the server isn't torn down before it applied the clipboard
+        while let Some((kind, payload)) = self.read_message().map_err(|e| e.to_string())? {
+            if kind == DEVICE_MSG_TYPE_ACK_CLIPBOARD && payload.len() == 8 && payload[..] == self.sequence.to_be_bytes() {
+                return Ok(());
+            }
+        }
+        Err("scrcpy-server closed the connection".to_string())
+    }
+
+    pub fn get_clipboard(&mut self) -> Result<String, String> {
+        self.stream.write_all(&[TYPE_GET_CLIPBOARD, 0]).map_err(|e| e.to_string())?;
+        while let Some((kind, payload)) = self.read_message().map_err(|e| e.to_string())? {
+            if kind == DEVICE_MSG_TYPE_CLIPBOARD {
+                return Ok(String::from_utf8_lossy(&payload).to_string());
+            }
+        }
+        Err("scrcpy-server closed the connection".to_string())
+    }
+
+    /// Reads one device message, `None` once the server closed the socket.
+    pub fn read_message(&mut self) -> std::io::Result<Option<(u8, Vec<u8>)>> {
+        let mut kind = [0u8; 1];
+        match self.stream.read_exact(&mut kind) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let payload = match kind[0] {
+            DEVICE_MSG_TYPE_CLIPBOARD => {
+                let mut length = [0u8; 4];
+                self.stream.read_exact(&mut length)?;
+                let mut text = vec![0u8; u32::from_be_bytes(length) as usize];
+                self.stream.read_exact(&mut text)?;
+                text
+            }
+            DEVICE_MSG_TYPE_ACK_CLIPBOARD => {
+                let mut sequence = vec![0u8; 8];
+                self.stream.read_exact(&mut sequence)?;
+                sequence
+            }
+            DEVICE_MSG_TYPE_UHID_OUTPUT => {
+                let mut header = [0u8; 4]; // id: u16, size: u16
+                self.stream.read_exact(&mut header)?;
+                let mut data = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+                self.stream.read_exact(&mut data)?;
+                data
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((kind[0], payload)))
+    }
+}
+
+impl Drop for ScrcpyControl {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = adb_command(&self.device_id).args(["forward", "--remove", &format!("tcp:{}", self.port)]).output();
+    }
+}
+
+/// Emits `device-clipboard` whenever the device clipboard changes until `stop` is set.
+pub fn start_sync(handle: AppHandle, mut device: ADBServerDevice, device_id: String, stop: Arc<AtomicBool>, interval: Duration, use_cmd: bool) {
+    thread::spawn(move || {
+        let mut last = None;
+        let mut emit = |text: String| {
+            if last.as_ref() != Some(&text) {
+                last = Some(text.clone());
+                handle.emit("device-clipboard", ClipboardEvent { device_id: device_id.clone(), text }).unwrap();
+            }
+        };
+
+        if use_cmd {
+            while !stop.load(Ordering::Relaxed) {
+                let mut output = Vec::new();
+                if device.shell_command(&["cmd", "clipboard", "get-primary-clip"], &mut output).is_ok() {
+                    if let Ok(Some(text)) = parse_cmd_clipboard(&String::from_utf8_lossy(&output)) {
+                        emit(text);
+                    }
+                }
+                thread::sleep(interval);
+            }
+            return;
+        }
+
+        // scrcpy pushes clipboard changes itself with clipboard_autosync
+        let Ok(mut control) = ScrcpyControl::connect(&device_id, true) else { return };
+        control.set_read_timeout(Some(interval));
+        while !stop.load(Ordering::Relaxed) {
+            match control.read_message() {
+                Ok(Some((DEVICE_MSG_TYPE_CLIPBOARD, payload))) => emit(String::from_utf8_lossy(&payload).to_string()),
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_clip_text() {
+        assert_eq!(parse_cmd_clipboard("ClipData { text/plain {T:hello world} }\n"), Ok(Some("hello world".to_string())));
+    }
+
+    #[test]
+    fn empty_clipboard() {
+        assert_eq!(parse_cmd_clipboard(""), Ok(None));
+        assert_eq!(parse_cmd_clipboard("null\n"), Ok(None));
+    }
+
+    #[test]
+    fn detects_missing_cmd() {
+        for output in [
+            "Unknown command: get-primary-clip",
+            "cmd: Can't find service: clipboard",
+            "No shell command implementation.",
+            "/system/bin/sh: cmd: not found",
+            "/system/bin/sh: cmd: inaccessible or not found",
+        ] {
+            assert_eq!(parse_cmd_clipboard(output), Err(CmdClipboardError::Unavailable), "{}", output);
+            assert_eq!(check_cmd_clipboard_set(output), Err(CmdClipboardError::Unavailable), "{}", output);
+        }
+    }
+
+    #[test]
+    fn reports_exceptions() {
+        let output = "Exception occurred while executing 'get-primary-clip':\njava.lang.SecurityException: Calling uid 2000 does not have permission";
+        assert!(matches!(parse_cmd_clipboard(output), Err(CmdClipboardError::Failed(_))));
+        let output = "java.lang.SecurityException: Calling uid 2000 does not have permission";
+        assert!(matches!(check_cmd_clipboard_set(output), Err(CmdClipboardError::Failed(_))));
+    }
+
+    #[test]
+    fn set_succeeds_only_without_output() {
+        assert_eq!(check_cmd_clipboard_set("\n"), Ok(()));
+        assert!(matches!(check_cmd_clipboard_set("usage: cmd clipboard set-primary-clip TEXT"), Err(CmdClipboardError::Failed(_))));
+    }
+}
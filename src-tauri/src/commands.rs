@@ -1,20 +1,39 @@
-use std::{io::{BufRead, BufReader, Read, Write}, os, process::{Command, Output, Stdio}, sync::{mpsc, Arc, Mutex}, thread};
+use std::{collections::{BTreeMap, HashMap}, fs::File, io::{BufRead, BufReader, Read, Write}, os, process::{Command, Output, Stdio}, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex}, thread, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use adb_client::{ADBDeviceExt, ADBServer};
+use adb_client::{ADBDeviceExt, ADBServer, ADBServerDevice};
+use base64::{engine::general_purpose, Engine};
 use tauri::{AppHandle, Emitter, Manager};
 use which::which;
+use zip::ZipArchive;
 
-use crate::utils::{get_app_detail_from_apk, get_app_detail_from_dir, get_app_detail_from_xapk, get_scrcpy, parse_ls_output, run_java_tool, AppDetail, Directory};
+use crate::{apksig::{self, SignatureReport}, jobs::{self, JobQueue}, arsc::{ResolvedResource, ResourceConfig}, manifest::{self, ManifestAnalysis}, backup::{self, BackupMetadata, BackupMethod}, broadcast::{self, BroadcastOperation}, bugreport::{self, BugreportIndex}, capture::{self, CaptureProgress, PacketCapture, REMOTE_TCPDUMP, REMOTE_TCPDUMP_PID}, registry::{DeviceProfile, DeviceRegistry}, signing::{self, SigningOptions}, zipalign::{self, AlignmentReport}, keystore::{KeyAlias, KeystoreInfo, KeystoreManager, KeystoreOptions, SigningKey, SigningProfile}, pipeline::{self, PipelineOptions}, cert, clipboard::{self, CmdClipboardError, ScrcpyControl, REMOTE_SCRCPY_SERVER}, sampler::{self, PerfSample, PerfSampler}, simulation::{self, BatterySimulation, Connectivity, MockLocation, SimulationState}, utils::{self, adb_command, build_intent_args, check_shell_output, get_app_detail_from_apk, get_app_detail_from_dir, get_app_detail_from_xapk, get_scrcpy, get_scrcpy_server, install_staged_apk, parse_appops_output, parse_intent_output, parse_ls_output, parse_package_permissions, parse_package_uids, parse_ps_output, parse_setting_value, parse_settings_list, parse_ui_hierarchy, parse_users, parse_package_list, read_apk_manifest, read_apk_resources, read_dir_manifest, read_xapk_base, user_args, quick_setting_commands, shell_quote, AppDetail, AppOp, Directory, Intent, IntentResult, PackagePermissions, ProcessInfo, QuickSetting, QuickSettings, RestoreReport, Setting, SettingsNamespace, SettingsSnapshot, UiHierarchy, UserInfo, PackageEntry}};
 
 pub struct AppData {
     pub adb_server: Mutex<ADBServer>,
+    pub samplers: Mutex<HashMap<String, PerfSampler>>,
+    pub captures: Mutex<HashMap<String, PacketCapture>>,
+    pub simulations: Mutex<HashMap<String, SimulationState>>,
+    pub clipboard_syncs: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    pub registry: Mutex<DeviceRegistry>,
+    pub keystores: Mutex<KeystoreManager>,
+    pub jobs: JobQueue,
+}
+
+#[derive(serde::Serialize)]
+pub struct CaInstallResult {
+    pub subject_hash: String,
+    pub location: String,
+    pub requires_user_action: bool,
 }
 
 #[derive(serde::Serialize)]
 pub struct Device {
     pub id: String,
+    pub serial: String,
     pub model: String,
     pub state: String,
+    #[serde(flatten)]
+    pub profile: DeviceProfile,
 }
 
 struct Reader {
@@ -45,6 +64,22 @@ impl Write for Writer {
     }
 }
 
+/// Reports a device that went away as an error instead of panicking.
+pub fn try_get_device(handle: &AppHandle, device_id: &str) -> Result<ADBServerDevice, String> {
+    handle.state::<AppData>().adb_server.lock().unwrap().get_device_by_name(device_id).map_err(|e| e.to_string())
+}
+
+fn shell(handle: &AppHandle, device_id: &str, command: &[&str]) -> Result<String, String> {
+    let mut device = try_get_device(handle, device_id)?;
+    let mut output = Vec::new();
+    device.shell_command(command, &mut output).map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+fn as_root(handle: &AppHandle, device_id: &str, command: &str) -> Result<Option<String>, String> {
+    utils::as_root(command, |command| shell(handle, device_id, &[command]))
+}
+
 #[tauri::command]
 pub fn hook_shell(handle: AppHandle, device_id: String) {
     let mut device = handle.state::<AppData>().adb_server.lock().unwrap().get_device_by_name(&device_id).expect("Can't get device by name");
@@ -94,8 +129,10 @@ pub fn get_adb_devices(handle: AppHandle) -> Vec<Device> {
     let data = handle.state::<AppData>();
     let mut adb_server = data.adb_server.lock().unwrap();
     let devices = adb_server.devices().expect("Can't fetch devices from ADB");
+    let mut registry = data.registry.lock().unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
 
-    devices.iter().map(|data| {
+    let devices = devices.iter().map(|data| {
         let id = data.identifier.clone();
         let mut device = adb_server.get_device_by_name(&id).expect("Can't get device by name");
         let mut output = Vec::new();
@@ -108,61 +145,161 @@ pub fn get_adb_devices(handle: AppHandle) -> Vec<Device> {
         if device.shell_command(&["getprop", "ro.product.product.device"], &mut output).is_ok() {
             product_device = String::from_utf8_lossy(&output).trim().to_string();
         }
+        output.clear();
+        let mut serial = id.clone();
+        if device.shell_command(&["getprop", "ro.serialno"], &mut output).is_ok() {
+            let value = String::from_utf8_lossy(&output).trim().to_string();
+            if !value.is_empty() {
+                serial = value;
+            }
+        }
 
         Device {
+            profile: registry.touch(&serial, &id, now),
             id,
+            serial,
             model: if model.is_empty() { product_device.clone() } else { model },
             state: data.state.to_string()
             
         }
-    }).collect::<Vec<Device>>()
+    }).collect::<Vec<Device>>();
+
+    let _ = registry.save();
+    devices
+}
+
+/// Signs an APK with the signing profile of its project, `password` is needed when the keystore password isn't saved.
+/// The outcome is emitted as `sign-finished`.
+#[tauri::command]
+pub fn sign_apk(handle: AppHandle, apk_path: String, project: Option<String>, password: Option<String>, options: Option<SigningOptions>) -> Result<(), String> {
+    // Apps compiled by Tuyu are written to compiled/<project>.apk
+    let project = project
+        .or(std::path::Path::new(&apk_path).file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .unwrap_or_default();
+    let options = options.unwrap_or_default();
+    let (key, previous) = signing_keys(&handle, &project, password, &options)?;
+    signing::sign(handle, apk_path, key, previous, options);
+    Ok(())
+}
+
+/// The key of a project and, when rotating keys, the previous key from the lineage options.
+fn signing_keys(handle: &AppHandle, project: &str, password: Option<String>, options: &SigningOptions) -> Result<(SigningKey, Option<SigningKey>), String> {
+    let data = handle.state::<AppData>();
+    let keystores = data.keystores.lock().unwrap();
+    let key = keystores.signing_key(project, password)?;
+    let previous = options.lineage.as_ref()
+        .map(|lineage| keystores.profile_key(&lineage.previous, lineage.previous_password.clone()))
+        .transpose()?;
+    Ok((key, previous))
+}
+
+/// Builds, aligns, signs, verifies and optionally installs a decompiled app as compiled/<name>.apk, stopping at the first failure.
+/// Steps are reported through `pipeline-progress` and `pipeline-finished`.
+#[tauri::command]
+pub fn rebuild_app(handle: AppHandle, app_path: String, name: String, password: Option<String>, options: Option<PipelineOptions>) -> Result<(), String> {
+    let options = options.unwrap_or_default();
+    let (key, previous) = signing_keys(&handle, &name, password, &options.signing)?;
+    pipeline::run(handle, app_path, name, key, previous, options);
+    Ok(())
+}
+
+/// Aligns uncompressed entries of an APK, in place when `output_path` is unset. `page_size` is in KiB like `zipalign -P`.
+#[tauri::command]
+pub fn align_apk(apk_path: String, output_path: Option<String>, page_size: Option<u32>) -> Result<AlignmentReport, String> {
+    let output_path = output_path.unwrap_or(apk_path.clone());
+    zipalign::align(std::path::Path::new(&apk_path), std::path::Path::new(&output_path), page_size)?;
+    zipalign::verify(std::path::Path::new(&output_path), page_size)
+}
+
+#[tauri::command]
+pub fn verify_alignment(apk_path: String, page_size: Option<u32>) -> Result<AlignmentReport, String> {
+    zipalign::verify(std::path::Path::new(&apk_path), page_size)
+}
+
+#[tauri::command]
+pub fn list_keystores(handle: AppHandle) -> Vec<KeystoreInfo> {
+    handle.state::<AppData>().keystores.lock().unwrap().list()
+}
+
+#[tauri::command]
+pub fn generate_keystore(handle: AppHandle, options: KeystoreOptions) -> Result<KeystoreInfo, String> {
+    handle.state::<AppData>().keystores.lock().unwrap().generate(options)
+}
+
+#[tauri::command]
+pub fn import_keystore(handle: AppHandle, name: String, path: String, password: String, remember_password: bool) -> Result<KeystoreInfo, String> {
+    handle.state::<AppData>().keystores.lock().unwrap().import(name, std::path::Path::new(&path), password, remember_password)
+}
+
+#[tauri::command]
+pub fn remove_keystore(handle: AppHandle, name: String) -> Result<(), String> {
+    handle.state::<AppData>().keystores.lock().unwrap().remove(&name)
+}
+
+#[tauri::command]
+pub fn list_keystore_aliases(handle: AppHandle, name: String, password: Option<String>) -> Result<Vec<KeyAlias>, String> {
+    handle.state::<AppData>().keystores.lock().unwrap().aliases(&name, password)
 }
 
+/// Saves the passwords of a keystore obfuscated in the config, without `password` they are forgotten and asked for on use.
 #[tauri::command]
-pub fn sign_apk(handle: AppHandle, apk_path: String) {
-    run_java_tool(
-        handle,
-        "apksigner",
-        &["sign", "--ks-key-alias", "tuyu", "--ks-pass", "pass:tuyu123", "--ks", "binaries/tuyu.keystore", &apk_path],
-        "App signed successfully".to_string(),
-        "Failed to sign app".to_string(),
-    );
+pub fn set_keystore_password(handle: AppHandle, name: String, password: Option<String>, key_password: Option<String>) -> Result<(), String> {
+    handle.state::<AppData>().keystores.lock().unwrap().set_password(&name, password, key_password)
 }
 
 #[tauri::command]
-pub fn merge_xapk(handle: AppHandle, xapk_path: String, name: String) {
+pub fn get_signing_profile(handle: AppHandle, project: String) -> Option<SigningProfile> {
+    handle.state::<AppData>().keystores.lock().unwrap().profile(&project)
+}
+
+#[tauri::command]
+pub fn set_signing_profile(handle: AppHandle, project: String, profile: Option<SigningProfile>) -> Result<(), String> {
+    handle.state::<AppData>().keystores.lock().unwrap().set_profile(project, profile)
+}
+
+#[tauri::command]
+pub fn merge_xapk(handle: AppHandle, xapk_path: String, name: String) -> u64 {
     let output_path = format!("compiled/{}.apk", name);
-    run_java_tool(
-        handle,
+    jobs::run_java_tool(
+        &handle,
         "apkeditor",
         &["m", "-i", &xapk_path, "-o", &output_path, "-f"],
+        &[],
         "XAPK merged successfully".to_string(),
         "Failed to merge XAPK".to_string(),
     )
 }
 
 #[tauri::command]
-pub fn extract_app(handle: AppHandle, app_path: String, name: String) {
+pub fn extract_app(handle: AppHandle, app_path: String, name: String) -> u64 {
     let output_path = format!("decompiled/{}", name);
-    run_java_tool(
-        handle,
+    jobs::run_java_tool(
+        &handle,
         "apktool",
         &["d", &app_path, "-o", &output_path, "-f"],
+        &[],
         "App decompiled successfully".to_string(),
         "Failed to decompile app".to_string(),
-    );
+    )
 }
 
 #[tauri::command]
-pub fn compile_app(handle: AppHandle, app_path: String, name: String) {
+pub fn compile_app(handle: AppHandle, app_path: String, name: String) -> u64 {
     let output_path = format!("compiled/{}.apk", name);
-    run_java_tool(
-        handle,
+    jobs::run_java_tool(
+        &handle,
         "apktool",
         &["b", &app_path, "-o", &output_path],
+        &[],
         "App compiled successfully".to_string(),
         "Failed to compile app".to_string(),
-    );
+    )
+}
+
+/// Kills a running Java tool job or drops it from the queue, `job-finished` reports it as cancelled.
+#[tauri::command]
+pub fn cancel_job(handle: AppHandle, id: u64) -> Result<(), String> {
+    handle.state::<AppData>().jobs.cancel(id)
 }
 
 #[tauri::command]
@@ -183,6 +320,65 @@ pub fn get_app_detail(app_path: String) -> Option<AppDetail> {
     }
 }
 
+/// Decodes the binary AndroidManifest.xml of an APK back to text XML.
+#[tauri::command]
+pub fn get_apk_manifest(app_path: String) -> Result<String, String> {
+    let file = File::open(&app_path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    read_apk_manifest(&mut archive).map(|manifest| manifest.to_xml()).ok_or("Invalid AndroidManifest.xml".to_string())
+}
+
+/// Lists permissions, components and security relevant flags of an APK, XAPK or decompiled app.
+#[tauri::command]
+pub fn analyze_manifest(app_path: String) -> Result<ManifestAnalysis, String> {
+    let path = std::path::Path::new(&app_path);
+    if path.is_dir() {
+        let manifest = read_dir_manifest(path).ok_or("Invalid AndroidManifest.xml".to_string())?;
+        return manifest::analyze(&manifest, None).ok_or("Manifest has no package".to_string());
+    }
+
+    fn analyze_apk<R: std::io::Read + std::io::Seek>(archive: &mut ZipArchive<R>) -> Result<ManifestAnalysis, String> {
+        let manifest = read_apk_manifest(archive).ok_or("Invalid AndroidManifest.xml".to_string())?;
+        let resources = read_apk_resources(archive);
+        manifest::analyze(&manifest, resources.as_ref()).ok_or("Manifest has no package".to_string())
+    }
+
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("xapk") | Some("apks") => analyze_apk(&mut read_xapk_base(&mut archive).ok_or("No base APK in bundle".to_string())?),
+        _ => analyze_apk(&mut archive),
+    }
+}
+
+/// Verifies the v1, v2, v3 and v3.1 signatures of an APK, or the base APK of an XAPK.
+#[tauri::command]
+pub fn verify_apk_signature(app_path: String) -> Result<SignatureReport, String> {
+    let path = std::path::Path::new(&app_path);
+    if path.is_dir() {
+        return Err("Decompiled apps are not signed".to_string());
+    }
+
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("xapk") | Some("apks") => {
+            let mut archive = ZipArchive::new(std::io::Cursor::new(data)).map_err(|e| e.to_string())?;
+            let base = read_xapk_base(&mut archive).ok_or("No base APK in bundle".to_string())?;
+            apksig::verify(base.into_inner().get_ref())
+        }
+        _ => apksig::verify(&data),
+    }
+}
+
+/// Resolves a resource id of an APK for a device config, e.g. the locale of a label or the density of an icon.
+#[tauri::command]
+pub fn resolve_resource(app_path: String, id: u32, config: Option<ResourceConfig>) -> Result<ResolvedResource, String> {
+    let file = File::open(&app_path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let resources = read_apk_resources(&mut archive).ok_or("Invalid resources.arsc".to_string())?;
+    resources.resolve(id, &config.unwrap_or_default()).ok_or(format!("Resource 0x{:08x} not found", id))
+}
+
 #[tauri::command]
 pub fn get_java() -> Option<String> {
     which("java").ok().map(|path| path.to_string_lossy().to_string())
@@ -191,4 +387,710 @@ pub fn get_java() -> Option<String> {
 #[tauri::command]
 pub fn get_adb() -> Option<String> {
     which("adb").ok().map(|path| path.to_string_lossy().to_string())
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub fn start_activity(handle: AppHandle, device_id: String, intent: Intent) -> Result<IntentResult, String> {
+    let args = build_intent_args(&intent);
+    let args = args.iter().map(|arg| arg.as_str()).collect::<Vec<&str>>();
+    Ok(parse_intent_output(&shell(&handle, &device_id, &args)?))
+}
+
+#[tauri::command]
+pub fn get_permissions(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>) -> Result<PackagePermissions, String> {
+    Ok(parse_package_permissions(&shell(&handle, &device_id, &["dumpsys", "package", &shell_quote(&package)])?, user_id.unwrap_or(0)))
+}
+
+#[tauri::command]
+pub fn grant_permission(handle: AppHandle, device_id: String, package: String, permission: String, user_id: Option<u32>) -> Result<(), String> {
+    let command = format!("pm grant {} {} {}", user_args(user_id).join(" "), shell_quote(&package), shell_quote(&permission));
+    check_shell_output(shell(&handle, &device_id, &[&command])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn revoke_permission(handle: AppHandle, device_id: String, package: String, permission: String, user_id: Option<u32>) -> Result<(), String> {
+    let command = format!("pm revoke {} {} {}", user_args(user_id).join(" "), shell_quote(&package), shell_quote(&permission));
+    check_shell_output(shell(&handle, &device_id, &[&command])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn reset_permissions(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>) -> Result<(), String> {
+    let permissions = get_permissions(handle.clone(), device_id.clone(), package.clone(), user_id)?;
+    let package = format!("{} {}", user_args(user_id).join(" "), shell_quote(&package));
+    // pm reset-permissions works on every package at once, so revoke and clear the user choices one by one
+    let script = permissions.runtime.iter().map(|permission| {
+        let permission = shell_quote(&permission.name);
+        format!("pm revoke {package} {permission} 2>&1; pm clear-permission-flags {package} {permission} user-set user-fixed 2>&1")
+    }).collect::<Vec<String>>().join("; ");
+
+    if script.is_empty() {
+        return Ok(());
+    }
+    check_shell_output(shell(&handle, &device_id, &[&script])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn get_appops(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>) -> Result<Vec<AppOp>, String> {
+    Ok(parse_appops_output(&shell(&handle, &device_id, &[&format!("appops get {} {}", user_args(user_id).join(" "), shell_quote(&package))])?))
+}
+
+#[tauri::command]
+pub fn set_appop(handle: AppHandle, device_id: String, package: String, op: String, mode: String, user_id: Option<u32>) -> Result<(), String> {
+    let command = format!("appops set {} {} {} {}", user_args(user_id).join(" "), shell_quote(&package), shell_quote(&op), shell_quote(&mode));
+    check_shell_output(shell(&handle, &device_id, &[&command])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn reset_appops(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>) -> Result<(), String> {
+    check_shell_output(shell(&handle, &device_id, &[&format!("appops reset {} {}", user_args(user_id).join(" "), shell_quote(&package))])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn list_settings(handle: AppHandle, device_id: String, namespace: SettingsNamespace) -> Result<Vec<Setting>, String> {
+    Ok(parse_settings_list(&shell(&handle, &device_id, &["settings", "list", namespace.as_str()])?))
+}
+
+#[tauri::command]
+pub fn get_setting(handle: AppHandle, device_id: String, namespace: SettingsNamespace, key: String) -> Result<Option<String>, String> {
+    Ok(parse_setting_value(&shell(&handle, &device_id, &["settings", "get", namespace.as_str(), &shell_quote(&key)])?))
+}
+
+#[tauri::command]
+pub fn put_setting(handle: AppHandle, device_id: String, namespace: SettingsNamespace, key: String, value: String) -> Result<(), String> {
+    check_shell_output(shell(&handle, &device_id, &["settings", "put", namespace.as_str(), &shell_quote(&key), &shell_quote(&value)])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn delete_setting(handle: AppHandle, device_id: String, namespace: SettingsNamespace, key: String) -> Result<(), String> {
+    check_shell_output(shell(&handle, &device_id, &["settings", "delete", namespace.as_str(), &shell_quote(&key)])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn get_quick_settings(handle: AppHandle, device_id: String) -> Result<QuickSettings, String> {
+    let keys = [
+        "global window_animation_scale",
+        "global transition_animation_scale",
+        "global animator_duration_scale",
+        "global stay_on_while_plugged_in",
+        "system show_touches",
+        "system pointer_location",
+        "system font_scale",
+        "system system_locales",
+    ];
+    let script = keys.iter().map(|key| format!("settings get {}", key)).chain(["cmd uimode night".to_string()]).collect::<Vec<String>>().join("; ");
+    let output = shell(&handle, &device_id, &[&script])?;
+    let values = output.lines().map(parse_setting_value).collect::<Vec<Option<String>>>();
+    let value = |index: usize| values.get(index).cloned().flatten();
+    let enabled = |index: usize| value(index).is_some_and(|v| v != "0");
+
+    Ok(QuickSettings {
+        window_animation_scale: value(0).and_then(|v| v.parse().ok()),
+        transition_animation_scale: value(1).and_then(|v| v.parse().ok()),
+        animator_duration_scale: value(2).and_then(|v| v.parse().ok()),
+        stay_awake: enabled(3),
+        show_taps: enabled(4),
+        pointer_location: enabled(5),
+        font_scale: value(6).and_then(|v| v.parse().ok()),
+        locale: value(7).filter(|v| !v.is_empty()),
+        dark_mode: value(8).is_some_and(|v| v.trim().ends_with("yes")),
+    })
+}
+
+#[tauri::command]
+pub fn apply_quick_setting(handle: AppHandle, device_id: String, setting: QuickSetting) -> Result<(), String> {
+    let script = quick_setting_commands(&setting).join(" && ");
+    check_shell_output(shell(&handle, &device_id, &[&script])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn snapshot_settings(handle: AppHandle, device_id: String, path: String) -> Result<(), String> {
+    let mut snapshot = SettingsSnapshot {
+        device_id: device_id.clone(),
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        ..Default::default()
+    };
+
+    for namespace in SettingsNamespace::ALL {
+        let settings = list_settings(handle.clone(), device_id.clone(), namespace)?;
+        snapshot.namespace_mut(namespace).extend(settings.into_iter().map(|setting| (setting.key, setting.value)));
+    }
+
+    let data = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+    std::fs::write(&path, data).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn restore_settings(handle: AppHandle, device_id: String, path: String) -> Result<RestoreReport, String> {
+    let data = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let snapshot: SettingsSnapshot = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let mut report = RestoreReport::default();
+    let mut commands = Vec::new();
+    let mut targets = Vec::new();
+
+    for namespace in SettingsNamespace::ALL {
+        let current = list_settings(handle.clone(), device_id.clone(), namespace)?;
+        for (key, value) in snapshot.namespace(namespace) {
+            if current.iter().any(|setting| &setting.key == key && &setting.value == value) {
+                report.unchanged += 1;
+                continue;
+            }
+            let target = format!("{}/{}", namespace.as_str(), key);
+            commands.push(format!(
+                "settings put {} {} {} >/dev/null 2>&1 || echo {}",
+                namespace.as_str(), shell_quote(key), shell_quote(value), shell_quote(&target)
+            ));
+            targets.push(target);
+        }
+    }
+
+    // Older adb servers cap the shell command length, so send the puts in batches
+    let mut batch = String::new();
+    for command in commands.iter() {
+        if !batch.is_empty() && batch.len() + command.len() > 3000 {
+            report.failed.extend(shell(&handle, &device_id, &[&batch])?.lines().map(|line| line.trim().to_string()));
+            batch.clear();
+        }
+        if !batch.is_empty() {
+            batch.push_str("; ");
+        }
+        batch.push_str(command);
+    }
+    if !batch.is_empty() {
+        report.failed.extend(shell(&handle, &device_id, &[&batch])?.lines().map(|line| line.trim().to_string()));
+    }
+    // Only the echoed targets count as failures, anything else the device prints is ignored
+    report.failed.retain(|target| targets.contains(target));
+    report.applied = commands.len().saturating_sub(report.failed.len());
+
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn list_processes(handle: AppHandle, device_id: String) -> Result<Vec<ProcessInfo>, String> {
+    let packages = parse_package_uids(&shell(&handle, &device_id, &["pm", "list", "packages", "-U"])?);
+    let output = shell(&handle, &device_id, &["ps", "-A", "-o", "PID,PPID,USER,RSS,%CPU,S,NAME"])?;
+    Ok(parse_ps_output(&output, &packages))
+}
+
+#[tauri::command]
+pub fn kill_process(handle: AppHandle, device_id: String, pid: u32, signal: Option<String>) -> Result<(), String> {
+    let signal = signal.unwrap_or("TERM".to_string());
+    let command = format!("kill -s {} {}", shell_quote(&signal), pid);
+    let output = shell(&handle, &device_id, &[&format!("{} 2>&1", command)])?;
+    if !output.contains("Operation not permitted") {
+        return check_kill_output(output);
+    }
+
+    // Processes of other apps can only be signalled as root
+    let command = as_root(&handle, &device_id, &command)?.ok_or("Signalling this process requires a rooted device")?;
+    check_kill_output(shell(&handle, &device_id, &[&format!("{} 2>&1", command)])?)
+}
+
+fn check_kill_output(output: String) -> Result<(), String> {
+    if output.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(output.trim().to_string())
+    }
+}
+
+#[tauri::command]
+pub fn kill_package(handle: AppHandle, device_id: String, package: String, force: bool) -> Result<(), String> {
+    let action = if force { "force-stop" } else { "kill" };
+    check_shell_output(shell(&handle, &device_id, &["am", action, &shell_quote(&package)])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn kill_background_processes(handle: AppHandle, device_id: String) -> Result<(), String> {
+    check_shell_output(shell(&handle, &device_id, &["am", "kill-all"])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn start_perf_sampler(handle: AppHandle, device_id: String, package: String, interval_ms: Option<u64>) -> Result<String, String> {
+    let uid = parse_package_uids(&shell(&handle, &device_id, &["pm", "list", "packages", "-U", &shell_quote(&package)])?)
+        .into_iter()
+        .find(|(_, packages)| packages.contains(&package))
+        .map(|(uid, _)| uid);
+    let session_id = format!("{}:{}:{}", device_id, package, SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis());
+    let interval = Duration::from_millis(interval_ms.unwrap_or(1000).max(250));
+
+    let device = try_get_device(&handle, &device_id)?;
+    let sampler = sampler::start(handle.clone(), device, session_id.clone(), package, uid, interval);
+    handle.state::<AppData>().samplers.lock().unwrap().insert(session_id.clone(), sampler);
+    Ok(session_id)
+}
+
+#[tauri::command]
+pub fn stop_perf_sampler(handle: AppHandle, session_id: String) -> usize {
+    let data = handle.state::<AppData>();
+    let samplers = data.samplers.lock().unwrap();
+    match samplers.get(&session_id) {
+        Some(sampler) => {
+            sampler.stop.store(true, Ordering::Relaxed);
+            sampler.samples.lock().unwrap().len()
+        }
+        None => 0,
+    }
+}
+
+#[tauri::command]
+pub fn get_perf_samples(handle: AppHandle, session_id: String) -> Vec<PerfSample> {
+    let data = handle.state::<AppData>();
+    let samplers = data.samplers.lock().unwrap();
+    samplers.get(&session_id).map(|sampler| sampler.samples.lock().unwrap().clone()).unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn export_perf_samples(handle: AppHandle, session_id: String, path: String) -> Result<(), String> {
+    let data = handle.state::<AppData>();
+    let samplers = data.samplers.lock().unwrap();
+    let sampler = samplers.get(&session_id).ok_or("Unknown sampler session")?;
+    let samples = sampler.samples.lock().unwrap();
+    sampler::write_csv(&samples, &path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_perf_sampler(handle: AppHandle, session_id: String) {
+    if let Some(sampler) = handle.state::<AppData>().samplers.lock().unwrap().remove(&session_id) {
+        sampler.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[tauri::command]
+pub fn dump_ui_hierarchy(handle: AppHandle, device_id: String) -> Result<Option<UiHierarchy>, String> {
+    let dump_path = "/data/local/tmp/tuyu_window_dump.xml";
+    let output = shell(&handle, &device_id, &[&format!("uiautomator dump {dump_path} >/dev/null 2>&1; cat {dump_path}; rm -f {dump_path}")])?;
+    let Some(mut hierarchy) = parse_ui_hierarchy(&output) else {
+        return Ok(None);
+    };
+
+    // exec-out keeps the PNG bytes intact, shell would mangle line endings on older devices
+    if let Ok(screenshot) = adb_command(&device_id).args(["exec-out", "screencap", "-p"]).output() {
+        if screenshot.status.success() && !screenshot.stdout.is_empty() {
+            hierarchy.screenshot_base64 = Some(general_purpose::STANDARD.encode(&screenshot.stdout));
+        }
+    }
+
+    Ok(Some(hierarchy))
+}
+
+#[tauri::command]
+pub fn input_tap(handle: AppHandle, device_id: String, x: i32, y: i32) -> Result<(), String> {
+    check_shell_output(shell(&handle, &device_id, &["input", "tap", &x.to_string(), &y.to_string()])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn input_swipe(handle: AppHandle, device_id: String, from: [i32; 2], to: [i32; 2], duration_ms: Option<u32>) -> Result<(), String> {
+    let duration = duration_ms.unwrap_or(300).to_string();
+    let args = [from[0].to_string(), from[1].to_string(), to[0].to_string(), to[1].to_string(), duration];
+    let args = ["input", "swipe"].into_iter().chain(args.iter().map(|arg| arg.as_str())).collect::<Vec<&str>>();
+    check_shell_output(shell(&handle, &device_id, &args)?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn capture_bugreport(handle: AppHandle, device_id: String, dest: String) {
+    bugreport::capture(handle, device_id, dest);
+}
+
+#[tauri::command]
+pub fn index_bugreport(path: String) -> Option<BugreportIndex> {
+    bugreport::index(&path)
+}
+
+#[tauri::command]
+pub fn read_bugreport_entry(path: String, name: String, start_line: Option<usize>, end_line: Option<usize>) -> Option<String> {
+    bugreport::read(&path, &name, start_line, end_line)
+}
+
+#[tauri::command]
+pub fn start_packet_capture(handle: AppHandle, device_id: String, interface: Option<String>, filter: Option<String>, dest: String) -> Result<(), String> {
+    if handle.state::<AppData>().captures.lock().unwrap().get(&device_id).is_some_and(|capture| capture.running.load(Ordering::Relaxed)) {
+        return Err("A capture is already running on this device".to_string());
+    }
+
+    let mut tcpdump = format!("{} -i {} -s 0 -U -w -", REMOTE_TCPDUMP, shell_quote(interface.as_deref().unwrap_or("any")));
+    if let Some(filter) = filter.filter(|filter| !filter.trim().is_empty()) {
+        tcpdump.push(' ');
+        tcpdump.push_str(&shell_quote(&filter));
+    }
+    // exec keeps the pid written by the shell, so stop can signal this tcpdump and no other
+    let command = as_root(&handle, &device_id, &format!("echo $$ > {} && exec {}", REMOTE_TCPDUMP_PID, tcpdump))?.ok_or("Packet capture requires a rooted device")?;
+    let read_pid = as_root(&handle, &device_id, &format!("cat {} 2>/dev/null", REMOTE_TCPDUMP_PID))?.ok_or("Packet capture requires a rooted device")?;
+
+    // A static tcpdump is bundled next to scrcpy-server
+    let mut file = std::fs::File::open("binaries/tcpdump").map_err(|_| "tcpdump not found")?;
+    let mut device = try_get_device(&handle, &device_id)?;
+    device.push(&mut file, &REMOTE_TCPDUMP).map_err(|e| e.to_string())?;
+    shell(&handle, &device_id, &[&format!("chmod 755 {} && rm -f {}", REMOTE_TCPDUMP, REMOTE_TCPDUMP_PID)])?;
+
+    let capture = capture::start(handle.clone(), device_id.clone(), command, dest, || {
+        shell(&handle, &device_id, &[&read_pid]).ok()?.trim().parse().ok()
+    })?;
+    handle.state::<AppData>().captures.lock().unwrap().insert(device_id, capture);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_packet_capture(handle: AppHandle, device_id: String) -> Result<CaptureProgress, String> {
+    let mut capture = handle.state::<AppData>().captures.lock().unwrap().remove(&device_id).ok_or("No capture running on this device")?;
+
+    // Stop tcpdump on the device first so it flushes the last packets through exec-out
+    if let Some(command) = as_root(&handle, &device_id, &format!("kill -INT {}; rm -f {}", capture.pid, REMOTE_TCPDUMP_PID))? {
+        shell(&handle, &device_id, &[&command])?;
+    }
+    for _ in 0..20 {
+        if !capture.running.load(Ordering::Relaxed) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = capture.child.kill();
+    let _ = capture.child.wait();
+
+    Ok(CaptureProgress {
+        device_id,
+        packets: capture.packets.load(Ordering::Relaxed),
+        bytes: capture.bytes.load(Ordering::Relaxed),
+        running: false,
+    })
+}
+
+#[tauri::command]
+pub fn set_http_proxy(handle: AppHandle, device_id: String, host: Option<String>, port: u16, reverse: bool) -> Result<(), String> {
+    let host = if reverse {
+        // The device reaches the host proxy through an adb reverse tunnel on the same port
+        let output = adb_command(&device_id).args(["reverse", &format!("tcp:{}", port), &format!("tcp:{}", port)]).output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        "127.0.0.1".to_string()
+    } else {
+        host.ok_or("A proxy host is required without reverse")?
+    };
+
+    check_shell_output(shell(&handle, &device_id, &["settings", "put", "global", "http_proxy", &shell_quote(&format!("{}:{}", host, port))])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn clear_http_proxy(handle: AppHandle, device_id: String) -> Result<(), String> {
+    let current = get_setting(handle.clone(), device_id.clone(), SettingsNamespace::Global, "http_proxy".to_string())?;
+    if let Some((host, port)) = current.as_deref().and_then(|proxy| proxy.rsplit_once(':')) {
+        if host == "127.0.0.1" || host == "localhost" {
+            let _ = adb_command(&device_id).args(["reverse", "--remove", &format!("tcp:{}", port)]).output();
+        }
+    }
+
+    // Deleting the key leaves the old proxy active until reboot, ":0" clears it immediately
+    check_shell_output(shell(&handle, &device_id, &["settings", "put", "global", "http_proxy", ":0"])?)?;
+    shell(&handle, &device_id, &["settings delete global global_http_proxy_host; settings delete global global_http_proxy_port"])?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn install_ca_certificate(handle: AppHandle, device_id: String, cert_path: String, system: bool, user_id: Option<u32>) -> Result<CaInstallResult, String> {
+    let data = std::fs::read(&cert_path).map_err(|e| e.to_string())?;
+    let der = cert::load_certificate(&data).ok_or("Not a PEM or DER certificate")?;
+    let subject_hash = cert::subject_hash_old(&der).ok_or("Failed to parse certificate subject")?;
+    let file_name = format!("{}.0", subject_hash);
+
+    let staged = format!("/data/local/tmp/{}", file_name);
+    let mut device = try_get_device(&handle, &device_id)?;
+    device.push(&mut cert::to_pem(&der).as_bytes(), &staged).map_err(|e| e.to_string())?;
+
+    if system {
+        // /system is read only, so the store is copied onto a tmpfs mounted over it until the next reboot.
+        // Android 14 reads the conscrypt APEX store instead, a bind mount from the adb shell isn't visible to apps,
+        // so it is made inside the mount namespace of zygote and of every app already forked from it.
+        let script = [
+            "set -e",
+            "exec 2>&1",
+            &format!("staged={}", staged),
+            "store=/system/etc/security/cacerts",
+            "apex=/apex/com.android.conscrypt/cacerts",
+            "src=$store",
+            "if [ -d $apex ]; then src=$apex; fi",
+            "rm -rf /data/local/tmp/tuyu-cacerts",
+            "mkdir -p /data/local/tmp/tuyu-cacerts",
+            "cp $src/* /data/local/tmp/tuyu-cacerts/",
+            "cp $staged /data/local/tmp/tuyu-cacerts/",
+            "mount -t tmpfs tmpfs $store",
+            "cp /data/local/tmp/tuyu-cacerts/* $store/",
+            "chown root:root $store/*",
+            "chmod 644 $store/*",
+            "chcon u:object_r:system_file:s0 $store/*",
+            "if [ -d $apex ]; then",
+            "  zygotes=$(pidof zygote zygote64 || true)",
+            "  for pid in $zygotes; do nsenter --mount=/proc/$pid/ns/mnt -- mount --bind $store $apex; done",
+            "  for pid in $(for zygote in $zygotes; do ps -o PID= -P $zygote; done); do",
+            "    nsenter --mount=/proc/$pid/ns/mnt -- mount --bind $store $apex || true",
+            "  done",
+            "fi",
+            "rm -rf /data/local/tmp/tuyu-cacerts $staged",
+            "echo done",
+        ].join("\n");
+        let command = as_root(&handle, &device_id, &script)?.ok_or("Installing a system CA requires a rooted device")?;
+        let output = shell(&handle, &device_id, &[&command])?;
+        if output.trim().lines().last() != Some("done") {
+            return Err(output.trim().to_string());
+        }
+
+        return Ok(CaInstallResult {
+            subject_hash,
+            location: format!("/system/etc/security/cacerts/{}", file_name),
+            requires_user_action: false,
+        });
+    }
+
+    // With root the user store can be written directly
+    let user_dir = format!("/data/misc/user/{}/cacerts-added", user_id.unwrap_or(0));
+    let user_store = format!("{}/{}", user_dir, file_name);
+    let script = format!("mkdir -p {user_dir} && cp {staged} {user_store} && chown system:system {user_store} && chmod 644 {user_store} && rm {staged} && echo done");
+    if let Some(command) = as_root(&handle, &device_id, &script)? {
+        if shell(&handle, &device_id, &[&format!("{} 2>&1", command)])?.trim().lines().last() == Some("done") {
+            return Ok(CaInstallResult {
+                subject_hash,
+                location: user_store,
+                requires_user_action: false,
+            });
+        }
+    }
+
+    // Android 11+ only installs CA certificates picked from Settings, the certificate is left in Download for that
+    let download = format!("/sdcard/Download/{}.crt", subject_hash);
+    shell(&handle, &device_id, &["mv", &staged, &download])?;
+    handle.emit("log", format!(
+        "Install {}.crt from Download on the device in Settings > Security > Encryption & credentials > Install a certificate > CA certificate",
+        subject_hash
+    )).unwrap();
+    Ok(CaInstallResult {
+        subject_hash,
+        location: download,
+        requires_user_action: true,
+    })
+}
+
+fn run_simulation_commands(handle: &AppHandle, device_id: &str, commands: Vec<String>) -> Result<(), String> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+    check_shell_output(shell(handle, device_id, &[&format!("({}) 2>&1", commands.join(" && "))])?).map(|_| ())
+}
+
+#[tauri::command]
+pub fn simulate_battery(handle: AppHandle, device_id: String, simulation: BatterySimulation) -> Result<(), String> {
+    run_simulation_commands(&handle, &device_id, simulation::battery_commands(&simulation))?;
+    handle.state::<AppData>().simulations.lock().unwrap().entry(device_id).or_default().battery = true;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn reset_battery(handle: AppHandle, device_id: String) -> Result<(), String> {
+    run_simulation_commands(&handle, &device_id, vec!["dumpsys battery reset".to_string()])?;
+    if let Some(state) = handle.state::<AppData>().simulations.lock().unwrap().get_mut(&device_id) {
+        state.battery = false;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_connectivity(handle: AppHandle, device_id: String) -> Result<Connectivity, String> {
+    let output = shell(&handle, &device_id, &["settings get global wifi_on; settings get global mobile_data; settings get global airplane_mode_on"])?;
+    Ok(simulation::parse_connectivity(&output))
+}
+
+#[tauri::command]
+pub fn set_connectivity(handle: AppHandle, device_id: String, connectivity: Connectivity) -> Result<(), String> {
+    let original = get_connectivity(handle.clone(), device_id.clone())?;
+    run_simulation_commands(&handle, &device_id, simulation::connectivity_commands(&connectivity))?;
+    // Only the state from before the first change is kept, so reset goes back to where the tester started
+    handle.state::<AppData>().simulations.lock().unwrap().entry(device_id).or_default().original_connectivity.get_or_insert(original);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_mock_location(handle: AppHandle, device_id: String, location: MockLocation) -> Result<(), String> {
+    run_simulation_commands(&handle, &device_id, simulation::mock_location_commands(&location))?;
+    handle.state::<AppData>().simulations.lock().unwrap().entry(device_id).or_default().mock_location = true;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn clear_mock_location(handle: AppHandle, device_id: String) -> Result<(), String> {
+    // remove-test-provider fails for providers that were never added, so errors are ignored here
+    shell(&handle, &device_id, &[&simulation::reset_mock_location_commands().join("; ")])?;
+    if let Some(state) = handle.state::<AppData>().simulations.lock().unwrap().get_mut(&device_id) {
+        state.mock_location = false;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_simulation_state(handle: AppHandle, device_id: String) -> SimulationState {
+    handle.state::<AppData>().simulations.lock().unwrap().get(&device_id).cloned().unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn reset_simulations(handle: AppHandle, device_id: String) -> Result<(), String> {
+    let state = handle.state::<AppData>().simulations.lock().unwrap().remove(&device_id).unwrap_or_default();
+    let mut commands = Vec::new();
+    if state.battery {
+        commands.push("dumpsys battery reset".to_string());
+    }
+    if let Some(original) = &state.original_connectivity {
+        commands.extend(simulation::connectivity_commands(original));
+    }
+    run_simulation_commands(&handle, &device_id, commands)?;
+    if state.mock_location {
+        clear_mock_location(handle, device_id)?;
+    }
+    Ok(())
+}
+
+fn push_scrcpy_server(handle: &AppHandle, device_id: &str) -> Result<(), String> {
+    let path = get_scrcpy_server().ok_or("scrcpy-server not found")?;
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    try_get_device(handle, device_id)?.push(&mut file, &REMOTE_SCRCPY_SERVER).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_device_clipboard(handle: AppHandle, device_id: String) -> Result<Option<String>, String> {
+    match clipboard::parse_cmd_clipboard(&shell(&handle, &device_id, &["cmd clipboard get-primary-clip 2>&1"])?) {
+        Ok(text) => return Ok(text),
+        Err(CmdClipboardError::Failed(error)) => return Err(error),
+        Err(CmdClipboardError::Unavailable) => {}
+    }
+
+    push_scrcpy_server(&handle, &device_id)?;
+    let mut control = ScrcpyControl::connect(&device_id, false)?;
+    control.set_read_timeout(Some(Duration::from_secs(5)));
+    control.get_clipboard().map(Some)
+}
+
+#[tauri::command]
+pub fn set_device_clipboard(handle: AppHandle, device_id: String, text: String) -> Result<(), String> {
+    let output = shell(&handle, &device_id, &["cmd", "clipboard", "set-primary-clip", &shell_quote(&text), "2>&1"])?;
+    match clipboard::check_cmd_clipboard_set(&output) {
+        Ok(()) => return Ok(()),
+        Err(CmdClipboardError::Failed(error)) => return Err(error),
+        Err(CmdClipboardError::Unavailable) => {}
+    }
+
+    push_scrcpy_server(&handle, &device_id)?;
+    let mut control = ScrcpyControl::connect(&device_id, false)?;
+    control.set_read_timeout(Some(Duration::from_secs(5)));
+    control.set_clipboard(&text)
+}
+
+#[tauri::command]
+pub fn start_clipboard_sync(handle: AppHandle, device_id: String, interval_ms: Option<u64>) -> Result<(), String> {
+    stop_clipboard_sync(handle.clone(), device_id.clone());
+
+    // Polling cmd is only worth it when it can read the clipboard, scrcpy-server covers the rest
+    let use_cmd = clipboard::parse_cmd_clipboard(&shell(&handle, &device_id, &["cmd clipboard get-primary-clip 2>&1"])?).is_ok();
+    if !use_cmd {
+        push_scrcpy_server(&handle, &device_id)?;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let interval = Duration::from_millis(interval_ms.unwrap_or(1000).max(250));
+    clipboard::start_sync(handle.clone(), try_get_device(&handle, &device_id)?, device_id.clone(), stop.clone(), interval, use_cmd);
+    handle.state::<AppData>().clipboard_syncs.lock().unwrap().insert(device_id, stop);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_clipboard_sync(handle: AppHandle, device_id: String) {
+    if let Some(stop) = handle.state::<AppData>().clipboard_syncs.lock().unwrap().remove(&device_id) {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[tauri::command]
+pub fn broadcast(handle: AppHandle, device_ids: Vec<String>, operation: BroadcastOperation) -> String {
+    let broadcast_id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string();
+    let devices = {
+        let data = handle.state::<AppData>();
+        let mut adb_server = data.adb_server.lock().unwrap();
+        device_ids.into_iter().map(|id| {
+            let device = adb_server.get_device_by_name(&id).map_err(|e| e.to_string());
+            (id, device)
+        }).collect::<Vec<_>>()
+    };
+
+    broadcast::run(handle.clone(), broadcast_id.clone(), devices, operation);
+    broadcast_id
+}
+
+#[tauri::command]
+pub fn get_device_profiles(handle: AppHandle) -> BTreeMap<String, DeviceProfile> {
+    handle.state::<AppData>().registry.lock().unwrap().devices.clone()
+}
+
+#[tauri::command]
+pub fn update_device_profile(handle: AppHandle, serial: String, alias: Option<String>, tags: Vec<String>, notes: String) -> Result<DeviceProfile, String> {
+    let data = handle.state::<AppData>();
+    let mut registry = data.registry.lock().unwrap();
+    let profile = registry.devices.entry(serial).or_default();
+    profile.alias = alias.filter(|alias| !alias.trim().is_empty());
+    profile.tags = tags;
+    profile.notes = notes;
+    let profile = profile.clone();
+    registry.save()?;
+    Ok(profile)
+}
+
+#[tauri::command]
+pub fn remove_device_profile(handle: AppHandle, serial: String) -> Result<(), String> {
+    let data = handle.state::<AppData>();
+    let mut registry = data.registry.lock().unwrap();
+    registry.devices.remove(&serial);
+    registry.save()
+}
+
+#[tauri::command]
+pub fn list_users(handle: AppHandle, device_id: String) -> Result<Vec<UserInfo>, String> {
+    Ok(parse_users(&shell(&handle, &device_id, &["pm", "list", "users"])?))
+}
+
+#[tauri::command]
+pub fn list_packages(handle: AppHandle, device_id: String, user_id: Option<u32>, third_party_only: bool) -> Result<Vec<PackageEntry>, String> {
+    let filter = if third_party_only { "-3" } else { "" };
+    Ok(parse_package_list(&shell(&handle, &device_id, &[&format!("pm list packages -f {} {}", filter, user_args(user_id).join(" "))])?))
+}
+
+#[tauri::command]
+pub fn install_apk(handle: AppHandle, device_id: String, apk_path: String, user_id: Option<u32>) -> Result<(), String> {
+    install_staged_apk(&mut try_get_device(&handle, &device_id)?, &apk_path, user_id).map(|_| ())
+}
+
+#[tauri::command]
+pub fn uninstall_package(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>, keep_data: bool) -> Result<(), String> {
+    let keep_data = if keep_data { "-k" } else { "" };
+    let output = shell(&handle, &device_id, &[&format!("pm uninstall {} {} {} 2>&1", keep_data, user_args(user_id).join(" "), shell_quote(&package))])?;
+    if output.trim() == "Success" {
+        Ok(())
+    } else {
+        Err(output.trim().to_string())
+    }
+}
+
+/// `command` is a raw shell fragment run after `run-as <package>`, it is not quoted so callers must quote its arguments.
+#[tauri::command]
+pub fn run_as(handle: AppHandle, device_id: String, package: String, command: String, user_id: Option<u32>) -> Result<String, String> {
+    shell(&handle, &device_id, &[&format!("run-as {} {} {} 2>&1", user_args(user_id).join(" "), shell_quote(&package), command)])
+}
+
+#[tauri::command]
+pub fn backup_app_data(handle: AppHandle, device_id: String, package: String, user_id: Option<u32>, method: Option<BackupMethod>, dest: String) {
+    backup::backup(handle, device_id, package, user_id, method, dest);
+}
+
+#[tauri::command]
+pub fn restore_app_data(handle: AppHandle, device_id: String, archive_path: String, user_id: Option<u32>) {
+    backup::restore(handle, device_id, archive_path, user_id);
+}
+
+#[tauri::command]
+pub fn read_backup_metadata(archive_path: String) -> Result<BackupMetadata, String> {
+    backup::read_metadata(&archive_path)
+}
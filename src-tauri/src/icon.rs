@@ -0,0 +1,556 @@
+//! Renders app icons that aren't plain PNGs: adaptive icons, vector drawables, shapes and WebP bitmaps.
+
+use std::{io::{Cursor, Read, Seek}, path::Path};
+
+use base64::{engine::general_purpose, Engine};
+use resvg::{tiny_skia, usvg};
+use roxmltree::Node;
+use zip::ZipArchive;
+
+use crate::{arsc::{ResourceConfig, ResourceTable, ResourceValue}, axml::{self, ANDROID_NS}, utils::{dir_density, lookup_dir_value}};
+
+const ICON_SIZE: u32 = 192;
+// Adaptive icon layers are 108dp, the mask shows the 72dp in the middle
+const LAYER_SIZE: f32 = 108.0;
+const VISIBLE_INSET: f32 = 18.0;
+
+pub enum Drawable {
+    Bitmap(Vec<u8>),
+    Xml(String),
+    Color(String),
+}
+
+/// Where the drawables and values referenced by an icon are loaded from.
+pub trait IconSource {
+    /// Resolves an `@` reference to a plain value such as a color.
+    fn resolve_value(&mut self, reference: &str) -> Option<String>;
+    fn load_drawable(&mut self, reference: &str) -> Option<Drawable>;
+}
+
+pub struct ApkIconSource<'a, R: Read + Seek> {
+    pub archive: &'a mut ZipArchive<R>,
+    pub resources: &'a ResourceTable,
+}
+
+fn reference_id(reference: &str) -> Option<u32> {
+    u32::from_str_radix(reference.strip_prefix("@0x")?, 16).ok()
+}
+
+impl<R: Read + Seek> IconSource for ApkIconSource<'_, R> {
+    fn resolve_value(&mut self, reference: &str) -> Option<String> {
+        self.resources.resolve_string(reference_id(reference)?, &ResourceConfig::default())
+    }
+
+    fn load_drawable(&mut self, reference: &str) -> Option<Drawable> {
+        let resolved = self.resources.resolve(reference_id(reference)?, &ResourceConfig::default())?;
+        match resolved.value {
+            ResourceValue::Color(color) => Some(Drawable::Color(color)),
+            ResourceValue::File(path) => {
+                let mut data = Vec::new();
+                self.archive.by_name(&path).ok()?.read_to_end(&mut data).ok()?;
+                if path.ends_with(".xml") {
+                    // Compiled XML is turned back into text so both sources share the same renderer
+                    Some(Drawable::Xml(axml::parse(&data)?.to_xml()))
+                } else {
+                    Some(Drawable::Bitmap(data))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct DirIconSource<'a> {
+    pub res_path: &'a Path,
+}
+
+impl IconSource for DirIconSource<'_> {
+    fn resolve_value(&mut self, reference: &str) -> Option<String> {
+        crate::utils::resolve_dir_value(self.res_path, reference)
+    }
+
+    fn load_drawable(&mut self, reference: &str) -> Option<Drawable> {
+        let mut reference = reference.to_string();
+        for _ in 0..16 {
+            let (res_type, name) = reference.strip_prefix('@')?.split_once('/')?;
+            // A modern device picks anydpi variants first, then the highest density
+            let file = std::fs::read_dir(self.res_path).ok()?
+                .flatten()
+                .filter_map(|entry| {
+                    let folder_name = entry.file_name().to_string_lossy().to_string();
+                    if folder_name != res_type && !folder_name.starts_with(&format!("{}-", res_type)) {
+                        return None;
+                    }
+                    let path = ["xml", "png", "webp", "jpg"].iter()
+                        .map(|ext| entry.path().join(format!("{}.{}", name, ext)))
+                        .find(|path| path.exists())?;
+                    Some(((folder_name.contains("anydpi"), dir_density(&folder_name)), path))
+                })
+                .max_by_key(|(rank, _)| *rank);
+
+            if let Some((_, path)) = file {
+                return if path.extension().is_some_and(|ext| ext == "xml") {
+                    std::fs::read_to_string(path).ok().map(Drawable::Xml)
+                } else {
+                    std::fs::read(path).ok().map(Drawable::Bitmap)
+                };
+            }
+
+            let value = lookup_dir_value(self.res_path, &reference)?;
+            if !value.starts_with('@') {
+                return Some(Drawable::Color(value));
+            }
+            reference = value;
+        }
+        None
+    }
+}
+
+/// Re-encodes WebP and JPEG bitmaps as PNG, PNGs are returned as is.
+pub fn to_png(data: Vec<u8>) -> Option<Vec<u8>> {
+    if image::guess_format(&data).ok()? == image::ImageFormat::Png {
+        return Some(data);
+    }
+    let image = image::load_from_memory(&data).ok()?;
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png).ok()?;
+    Some(png.into_inner())
+}
+
+/// Renders a drawable reference to PNG, as a launcher would show it.
+pub fn render_icon(source: &mut dyn IconSource, reference: &str) -> Option<Vec<u8>> {
+    let xml = match source.load_drawable(reference)? {
+        Drawable::Bitmap(data) => return to_png(data),
+        Drawable::Color(_) => return None,
+        Drawable::Xml(xml) => xml,
+    };
+    let doc = roxmltree::Document::parse(&xml).ok()?;
+    let root = doc.root_element();
+
+    let mut builder = SvgBuilder { source, defs: String::new(), next_id: 0, depth: 0 };
+    let (view_box, body) = if root.has_tag_name("adaptive-icon") {
+        let body = builder.adaptive_icon(root);
+        (format!("{0} {0} {1} {1}", VISIBLE_INSET, LAYER_SIZE - 2.0 * VISIBLE_INSET), body)
+    } else {
+        let size = ICON_SIZE as f32;
+        (format!("0 0 {0} {0}", size), builder.element(root, size, size))
+    };
+    if body.is_empty() {
+        return None;
+    }
+
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{0}" height="{0}" viewBox="{1}"><defs>{2}</defs>{3}</svg>"#,
+        ICON_SIZE, view_box, builder.defs, body
+    );
+    rasterize(&svg)
+}
+
+fn rasterize(svg: &str) -> Option<Vec<u8>> {
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default()).ok()?;
+    let mut pixmap = tiny_skia::Pixmap::new(ICON_SIZE, ICON_SIZE)?;
+    resvg::render(&tree, tiny_skia::Transform::identity(), &mut pixmap.as_mut());
+    pixmap.encode_png().ok()
+}
+
+enum Paint {
+    Solid(String, f32),
+    Url(String),
+}
+
+/// Translates drawable XML to SVG, gradients and clip paths are collected in `defs`.
+struct SvgBuilder<'a> {
+    source: &'a mut dyn IconSource,
+    defs: String,
+    next_id: usize,
+    depth: usize,
+}
+
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute((ANDROID_NS, name)).or(node.attribute(name))
+}
+
+/// Enum attributes are names in source XML and indexes in compiled XML.
+fn keyword<'a>(node: Node, name: &str, keywords: &[&'a str]) -> Option<&'a str> {
+    let value = attr(node, name)?;
+    match value.parse::<usize>() {
+        Ok(index) => keywords.get(index).copied(),
+        Err(_) => keywords.iter().find(|keyword| **keyword == value).copied(),
+    }
+}
+
+fn number(value: Option<&str>, default: f32) -> f32 {
+    value
+        .map(|value| value.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%'))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Parses `#RGB`, `#ARGB`, `#RRGGBB` and `#AARRGGBB` into an SVG color and opacity.
+fn parse_color(value: &str) -> Option<(String, f32)> {
+    let hex = value.strip_prefix('#')?;
+    let expand = |hex: &str| hex.chars().flat_map(|c| [c, c]).collect::<String>();
+    let argb = match hex.len() {
+        3 => format!("ff{}", expand(hex)),
+        4 => expand(hex),
+        6 => format!("ff{}", hex),
+        8 => hex.to_string(),
+        _ => return None,
+    };
+    let value = u32::from_str_radix(&argb, 16).ok()?;
+    Some((format!("#{:06x}", value & 0xffffff), (value >> 24) as f32 / 255.0))
+}
+
+/// The few framework colors icons commonly use.
+fn framework_color(reference: &str) -> Option<&'static str> {
+    match reference {
+        "@android:color/white" | "@0x0106000b" => Some("#ffffffff"),
+        "@android:color/black" | "@0x0106000c" => Some("#ff000000"),
+        "@android:color/transparent" | "@0x0106000d" => Some("#00000000"),
+        _ => None,
+    }
+}
+
+impl SvgBuilder<'_> {
+    fn id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    /// Draws a referenced drawable into a `width` x `height` box at the origin.
+    fn drawable(&mut self, reference: &str, width: f32, height: f32) -> String {
+        // Drawables can nest each other, the limit guards against cycles
+        if self.depth > 16 {
+            return String::new();
+        }
+        if let Some(color) = framework_color(reference) {
+            return self.rect(color, width, height);
+        }
+        match self.source.load_drawable(reference) {
+            Some(Drawable::Bitmap(data)) => match to_png(data) {
+                Some(png) => format!(
+                    r#"<image width="{}" height="{}" preserveAspectRatio="none" xlink:href="data:image/png;base64,{}"/>"#,
+                    width, height, general_purpose::STANDARD.encode(png)
+                ),
+                None => String::new(),
+            },
+            Some(Drawable::Color(color)) => self.rect(&color, width, height),
+            Some(Drawable::Xml(xml)) => {
+                let Ok(doc) = roxmltree::Document::parse(&xml) else { return String::new() };
+                self.depth += 1;
+                let svg = self.element(doc.root_element(), width, height);
+                self.depth -= 1;
+                svg
+            }
+            None => String::new(),
+        }
+    }
+
+    fn rect(&mut self, color: &str, width: f32, height: f32) -> String {
+        match parse_color(color) {
+            Some((fill, opacity)) => format!(r#"<rect width="{}" height="{}" fill="{}" fill-opacity="{}"/>"#, width, height, fill, opacity),
+            None => String::new(),
+        }
+    }
+
+    /// Draws either the `android:drawable` attribute or the inline child drawable of a node.
+    fn child_drawable(&mut self, node: Node, width: f32, height: f32) -> String {
+        match attr(node, "drawable") {
+            Some(reference) => self.drawable(reference, width, height),
+            None => match node.children().find(|child| child.is_element()) {
+                Some(child) => self.element(child, width, height),
+                None => String::new(),
+            },
+        }
+    }
+
+    fn element(&mut self, node: Node, width: f32, height: f32) -> String {
+        match node.tag_name().name() {
+            "vector" => self.vector(node, width, height),
+            "shape" => self.shape(node, width, height),
+            "adaptive-icon" => format!(
+                r#"<g transform="scale({},{})">{}</g>"#,
+                width / LAYER_SIZE, height / LAYER_SIZE, self.adaptive_icon(node)
+            ),
+            "bitmap" | "nine-patch" => attr(node, "src").map(|src| self.drawable(src, width, height)).unwrap_or_default(),
+            "color" => attr(node, "color").map(|color| self.rect(color, width, height)).unwrap_or_default(),
+            "layer-list" => node.children()
+                .filter(|child| child.has_tag_name("item"))
+                .map(|item| self.inset(item, width, height, ["left", "top", "right", "bottom"]))
+                .collect(),
+            "inset" => self.inset(node, width, height, ["insetLeft", "insetTop", "insetRight", "insetBottom"]),
+            "selector" => {
+                // Without a state the default item applies, which is the one without state attributes
+                let item = node.children()
+                    .filter(|child| child.has_tag_name("item"))
+                    .find(|item| !item.attributes().any(|a| a.name().starts_with("state_")));
+                item.map(|item| self.child_drawable(item, width, height)).unwrap_or_default()
+            }
+            "rotate" | "scale" | "clip" => self.child_drawable(node, width, height),
+            _ => String::new(),
+        }
+    }
+
+    fn inset(&mut self, node: Node, width: f32, height: f32, names: [&str; 4]) -> String {
+        let all = attr(node, "inset");
+        let inset = |name: &str, size: f32| {
+            let value = attr(node, name).or(all);
+            match value {
+                Some(value) if value.ends_with('%') => number(Some(value), 0.0) / 100.0 * size,
+                value => number(value, 0.0),
+            }
+        };
+        let (left, top) = (inset(names[0], width), inset(names[1], height));
+        let (right, bottom) = (inset(names[2], width), inset(names[3], height));
+        let content = self.child_drawable(node, (width - left - right).max(0.0), (height - top - bottom).max(0.0));
+        format!(r#"<g transform="translate({},{})">{}</g>"#, left, top, content)
+    }
+
+    fn adaptive_icon(&mut self, node: Node) -> String {
+        let mut layers = String::new();
+        for name in ["background", "foreground"] {
+            if let Some(layer) = node.children().find(|child| child.has_tag_name(name)) {
+                layers.push_str(&self.child_drawable(layer, LAYER_SIZE, LAYER_SIZE));
+            }
+        }
+        // Launchers use different mask shapes, the circle is the most common one
+        let mask = self.id("mask");
+        self.defs.push_str(&format!(
+            r#"<clipPath id="{}"><circle cx="{}" cy="{}" r="{}"/></clipPath>"#,
+            mask, LAYER_SIZE / 2.0, LAYER_SIZE / 2.0, LAYER_SIZE / 2.0 - VISIBLE_INSET
+        ));
+        format!(r#"<g clip-path="url(#{})">{}</g>"#, mask, layers)
+    }
+
+    fn vector(&mut self, node: Node, width: f32, height: f32) -> String {
+        let viewport_width = number(attr(node, "viewportWidth"), number(attr(node, "width"), width));
+        let viewport_height = number(attr(node, "viewportHeight"), number(attr(node, "height"), height));
+        if viewport_width <= 0.0 || viewport_height <= 0.0 {
+            return String::new();
+        }
+
+        let content = self.vector_children(node);
+        let mut svg = format!(
+            r#"<g transform="scale({},{})" opacity="{}">{}</g>"#,
+            width / viewport_width, height / viewport_height, number(attr(node, "alpha"), 1.0), content
+        );
+
+        // Tints replace the color of everything drawn while keeping its alpha
+        if let Some((color, opacity)) = attr(node, "tint").and_then(|tint| self.resolve_color(tint)) {
+            let filter = self.id("tint");
+            self.defs.push_str(&format!(
+                r#"<filter id="{}" x="0" y="0" width="1" height="1"><feFlood flood-color="{}" flood-opacity="{}"/><feComposite in2="SourceGraphic" operator="in"/></filter>"#,
+                filter, color, opacity
+            ));
+            svg = format!(r#"<g filter="url(#{})">{}</g>"#, filter, svg);
+        }
+        svg
+    }
+
+    fn vector_children(&mut self, node: Node) -> String {
+        let children: Vec<Node> = node.children().filter(|child| child.is_element()).collect();
+        self.vector_nodes(&children)
+    }
+
+    fn vector_nodes(&mut self, nodes: &[Node]) -> String {
+        let mut svg = String::new();
+        for (i, node) in nodes.iter().enumerate() {
+            match node.tag_name().name() {
+                "path" => svg.push_str(&self.path(*node)),
+                "group" => {
+                    let pivot_x = number(attr(*node, "pivotX"), 0.0);
+                    let pivot_y = number(attr(*node, "pivotY"), 0.0);
+                    let transform = format!(
+                        "translate({},{}) rotate({}) scale({},{}) translate({},{})",
+                        number(attr(*node, "translateX"), 0.0) + pivot_x,
+                        number(attr(*node, "translateY"), 0.0) + pivot_y,
+                        number(attr(*node, "rotation"), 0.0),
+                        number(attr(*node, "scaleX"), 1.0),
+                        number(attr(*node, "scaleY"), 1.0),
+                        -pivot_x,
+                        -pivot_y,
+                    );
+                    let content = self.vector_children(*node);
+                    svg.push_str(&format!(r#"<g transform="{}">{}</g>"#, transform, content));
+                }
+                "clip-path" => {
+                    // A clip path applies to the siblings that follow it in the same group
+                    let clip = self.id("clip");
+                    let path_data = escape(attr(*node, "pathData").unwrap_or_default());
+                    self.defs.push_str(&format!(r#"<clipPath id="{}"><path d="{}"/></clipPath>"#, clip, path_data));
+                    let rest = self.vector_nodes(&nodes[i + 1..]);
+                    svg.push_str(&format!(r#"<g clip-path="url(#{})">{}</g>"#, clip, rest));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        svg
+    }
+
+    fn path(&mut self, node: Node) -> String {
+        let Some(path_data) = attr(node, "pathData") else { return String::new() };
+        let mut svg = format!(r#"<path d="{}""#, escape(path_data));
+
+        let fill = attr(node, "fillColor").and_then(|color| self.paint(color, false));
+        match fill {
+            Some(Paint::Solid(color, opacity)) => svg.push_str(&format!(r#" fill="{}" fill-opacity="{}""#, color, opacity * number(attr(node, "fillAlpha"), 1.0))),
+            Some(Paint::Url(id)) => svg.push_str(&format!(r#" fill="url(#{})" fill-opacity="{}""#, id, number(attr(node, "fillAlpha"), 1.0))),
+            None => svg.push_str(r#" fill="none""#),
+        }
+        if keyword(node, "fillType", &["nonZero", "evenOdd"]) == Some("evenOdd") {
+            svg.push_str(r#" fill-rule="evenodd""#);
+        }
+
+        let stroke = attr(node, "strokeColor").and_then(|color| self.paint(color, false));
+        let stroke_width = number(attr(node, "strokeWidth"), 0.0);
+        if let (Some(stroke), true) = (stroke, stroke_width > 0.0) {
+            let stroke_alpha = number(attr(node, "strokeAlpha"), 1.0);
+            match stroke {
+                Paint::Solid(color, opacity) => svg.push_str(&format!(r#" stroke="{}" stroke-opacity="{}""#, color, opacity * stroke_alpha)),
+                Paint::Url(id) => svg.push_str(&format!(r#" stroke="url(#{})" stroke-opacity="{}""#, id, stroke_alpha)),
+            }
+            svg.push_str(&format!(
+                r#" stroke-width="{}" stroke-linecap="{}" stroke-linejoin="{}" stroke-miterlimit="{}""#,
+                stroke_width,
+                keyword(node, "strokeLineCap", &["butt", "round", "square"]).unwrap_or("butt"),
+                keyword(node, "strokeLineJoin", &["miter", "round", "bevel"]).unwrap_or("miter"),
+                number(attr(node, "strokeMiterLimit"), 4.0).max(1.0),
+            ));
+        }
+        svg.push_str("/>");
+        svg
+    }
+
+    fn resolve_color(&mut self, value: &str) -> Option<(String, f32)> {
+        match self.paint(value, false)? {
+            Paint::Solid(color, opacity) => Some((color, opacity)),
+            Paint::Url(_) => None,
+        }
+    }
+
+    /// Turns a color value, color reference or gradient reference into an SVG paint.
+    fn paint(&mut self, value: &str, bounding_box: bool) -> Option<Paint> {
+        if let Some((color, opacity)) = parse_color(value).or(framework_color(value).and_then(parse_color)) {
+            return Some(Paint::Solid(color, opacity));
+        }
+        if !value.starts_with('@') {
+            return None; // Theme attributes can't be resolved without the device theme
+        }
+        if let Some((color, opacity)) = self.source.resolve_value(value).as_deref().and_then(parse_color) {
+            return Some(Paint::Solid(color, opacity));
+        }
+
+        let Some(Drawable::Xml(xml)) = self.source.load_drawable(value) else { return None };
+        let doc = roxmltree::Document::parse(&xml).ok()?;
+        let root = doc.root_element();
+        match root.tag_name().name() {
+            "gradient" => self.gradient(root, bounding_box),
+            "selector" => {
+                let item = root.children()
+                    .filter(|child| child.has_tag_name("item"))
+                    .find(|item| !item.attributes().any(|a| a.name().starts_with("state_")))?;
+                let (color, opacity) = self.resolve_color(attr(item, "color")?)?;
+                Some(Paint::Solid(color, opacity * number(attr(item, "alpha"), 1.0)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Gradients of vectors use viewport coordinates, gradients of shapes are relative to the shape.
+    fn gradient(&mut self, node: Node, bounding_box: bool) -> Option<Paint> {
+        let mut stops: Vec<(f32, String, f32)> = Vec::new();
+        let items: Vec<Node> = node.children().filter(|child| child.has_tag_name("item")).collect();
+        if items.is_empty() {
+            let colors: Vec<&str> = ["startColor", "centerColor", "endColor"].iter().filter_map(|name| attr(node, name)).collect();
+            for (i, color) in colors.iter().enumerate() {
+                if let Some((color, opacity)) = self.resolve_color(color) {
+                    stops.push((i as f32 / (colors.len().max(2) - 1) as f32, color, opacity));
+                }
+            }
+        } else {
+            for item in items {
+                if let Some((color, opacity)) = attr(item, "color").and_then(|color| self.resolve_color(color)) {
+                    stops.push((number(attr(item, "offset"), 0.0), color, opacity));
+                }
+            }
+        }
+        let (_, first_color, first_opacity) = stops.first()?.clone();
+
+        let spread = match keyword(node, "tileMode", &["clamp", "repeat", "mirror"]) {
+            Some("repeat") => "repeat",
+            Some("mirror") => "reflect",
+            _ => "pad",
+        };
+        let units = if bounding_box { "objectBoundingBox" } else { "userSpaceOnUse" };
+        let id = self.id("gradient");
+        let stops: String = stops.iter()
+            .map(|(offset, color, opacity)| format!(r#"<stop offset="{}" stop-color="{}" stop-opacity="{}"/>"#, offset, color, opacity))
+            .collect();
+
+        let gradient = match keyword(node, "type", &["linear", "radial", "sweep"]).unwrap_or("linear") {
+            "linear" if bounding_box => {
+                // Shape gradients go from left to right, rotated counter-clockwise by the angle
+                let angle = number(attr(node, "angle"), 0.0).to_radians();
+                let (dx, dy) = (angle.cos() / 2.0, -angle.sin() / 2.0);
+                format!(
+                    r#"<linearGradient id="{}" gradientUnits="{}" spreadMethod="{}" x1="{}" y1="{}" x2="{}" y2="{}">{}</linearGradient>"#,
+                    id, units, spread, 0.5 - dx, 0.5 - dy, 0.5 + dx, 0.5 + dy, stops
+                )
+            }
+            "linear" => format!(
+                r#"<linearGradient id="{}" gradientUnits="{}" spreadMethod="{}" x1="{}" y1="{}" x2="{}" y2="{}">{}</linearGradient>"#,
+                id, units, spread,
+                number(attr(node, "startX"), 0.0), number(attr(node, "startY"), 0.0),
+                number(attr(node, "endX"), 0.0), number(attr(node, "endY"), 0.0),
+                stops
+            ),
+            "radial" => {
+                let default_center = if bounding_box { 0.5 } else { 0.0 };
+                let radius = number(attr(node, "gradientRadius"), if bounding_box { 0.5 } else { 0.0 });
+                format!(
+                    r#"<radialGradient id="{}" gradientUnits="{}" spreadMethod="{}" cx="{}" cy="{}" r="{}">{}</radialGradient>"#,
+                    id, units, spread,
+                    number(attr(node, "centerX"), default_center), number(attr(node, "centerY"), default_center),
+                    radius, stops
+                )
+            }
+            // Sweep gradients have no SVG equivalent, the first color is a close enough stand-in
+            _ => return Some(Paint::Solid(first_color, first_opacity)),
+        };
+        self.defs.push_str(&gradient);
+        Some(Paint::Url(id))
+    }
+
+    fn shape(&mut self, node: Node, width: f32, height: f32) -> String {
+        let fill = match (node.children().find(|c| c.has_tag_name("solid")), node.children().find(|c| c.has_tag_name("gradient"))) {
+            (_, Some(gradient)) => self.gradient(gradient, true),
+            (Some(solid), None) => attr(solid, "color").and_then(|color| self.paint(color, true)),
+            (None, None) => None,
+        };
+        let mut style = match fill {
+            Some(Paint::Solid(color, opacity)) => format!(r#" fill="{}" fill-opacity="{}""#, color, opacity),
+            Some(Paint::Url(id)) => format!(r#" fill="url(#{})""#, id),
+            None => r#" fill="none""#.to_string(),
+        };
+        if let Some(stroke) = node.children().find(|c| c.has_tag_name("stroke")) {
+            let stroke_width = number(attr(stroke, "width"), 0.0);
+            if let Some((color, opacity)) = attr(stroke, "color").and_then(|color| self.resolve_color(color)) {
+                style.push_str(&format!(r#" stroke="{}" stroke-opacity="{}" stroke-width="{}""#, color, opacity, stroke_width));
+            }
+        }
+
+        match keyword(node, "shape", &["rectangle", "oval", "line", "ring"]).unwrap_or("rectangle") {
+            "oval" => format!(r#"<ellipse cx="{0}" cy="{1}" rx="{0}" ry="{1}"{2}/>"#, width / 2.0, height / 2.0, style),
+            "rectangle" => {
+                let radius = node.children().find(|c| c.has_tag_name("corners")).map(|corners| number(attr(corners, "radius"), 0.0)).unwrap_or(0.0);
+                format!(r#"<rect width="{}" height="{}" rx="{}"{}/>"#, width, height, radius, style)
+            }
+            _ => String::new(),
+        }
+    }
+}
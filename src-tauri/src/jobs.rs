@@ -0,0 +1,201 @@
+use std::{collections::HashMap, io::{BufRead, BufReader, Read}, path::Path, process::{Child, Command, Stdio}, sync::{atomic::{AtomicU64, Ordering}, mpsc, Arc, Mutex}, time::{Duration, Instant}};
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::AppData;
+
+/// A `java -jar binaries/<tool>.jar` run, every job gets its own thread so a long build doesn't hold up signing.
+struct Job {
+    tool: String,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    messages: Option<(String, String)>, // logged on success and failure
+}
+
+impl Job {
+    fn new(tool_name: &str, args: &[&str], envs: &[(String, String)], messages: Option<(String, String)>) -> Self {
+        Job { tool: tool_name.to_string(), args: args.iter().map(|arg| arg.to_string()).collect(), envs: envs.to_vec(), messages }
+    }
+}
+
+/// Emitted for every job, including the ones signing and the rebuild pipeline wait on, so any of them can be cancelled.
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct JobQueued {
+    pub id: u64,
+    pub tool: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct JobProgress {
+    pub id: u64,
+    pub tool: String,
+    pub line: String,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct JobResult {
+    pub id: u64,
+    pub tool: String,
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub cancelled: bool,
+    pub duration_ms: u64,
+    pub output: String,
+}
+
+/// How much of the tool output ends up in `JobResult`, in bytes.
+const MAX_OUTPUT: usize = 64 * 1024;
+
+#[derive(Default)]
+struct JobState {
+    cancelled: bool,
+    child: Option<Arc<Mutex<Child>>>,
+}
+
+#[derive(Default)]
+pub struct JobQueue {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, JobState>>,
+}
+
+impl JobQueue {
+    /// Kills the child of a running job, a job that hasn't started yet is dropped.
+    pub fn cancel(&self, id: u64) -> Result<(), String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let state = jobs.get_mut(&id).ok_or(format!("Job {} is not queued or running", id))?;
+        state.cancelled = true;
+        if let Some(child) = &state.child {
+            child.lock().unwrap().kill().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn submit(handle: &AppHandle, job: Job, notify: Option<mpsc::Sender<JobResult>>) -> u64 {
+    let data = handle.state::<AppData>();
+    let queue = &data.jobs;
+    let id = queue.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    queue.jobs.lock().unwrap().insert(id, JobState::default());
+
+    handle.emit("job-queued", JobQueued { id, tool: job.tool.clone(), args: job.args.clone() }).unwrap();
+
+    let handle = handle.clone();
+    std::thread::spawn(move || {
+        let result = execute(&handle, id, &job);
+        handle.state::<AppData>().jobs.jobs.lock().unwrap().remove(&id);
+        if let Some((success_msg, error_msg)) = &job.messages {
+            handle.emit("log", if result.success { success_msg } else { error_msg }).unwrap();
+        }
+        handle.emit("job-finished", result.clone()).unwrap();
+        if let Some(notify) = notify {
+            let _ = notify.send(result);
+        }
+    });
+    id
+}
+
+/// Queues a jar from `binaries` and returns the job id, the outcome is emitted as `job-finished`.
+pub fn run_java_tool(handle: &AppHandle, tool_name: &str, args: &[&str], envs: &[(String, String)], success_msg: String, error_msg: String) -> u64 {
+    submit(handle, Job::new(tool_name, args, envs, Some((success_msg, error_msg))), None)
+}
+
+/// Queues a jar from `binaries` and waits for it, returning its output or what it printed on failure.
+/// The job id is only announced through `job-queued`.
+pub fn run_java_tool_blocking(handle: &AppHandle, tool_name: &str, args: &[&str], envs: &[(String, String)]) -> Result<String, String> {
+    let (sender, receiver) = mpsc::channel();
+    submit(handle, Job::new(tool_name, args, envs, None), Some(sender));
+    let result = receiver.recv().map_err(|e| e.to_string())?;
+    match result {
+        JobResult { success: true, output, .. } => Ok(output),
+        JobResult { cancelled: true, .. } => Err("Cancelled".to_string()),
+        JobResult { output, .. } => Err(output),
+    }
+}
+
+fn execute(handle: &AppHandle, id: u64, job: &Job) -> JobResult {
+    let start = Instant::now();
+    let mut result = JobResult { id, tool: job.tool.clone(), exit_code: None, success: false, cancelled: false, duration_ms: 0, output: String::new() };
+    let data = handle.state::<AppData>();
+    let queue = &data.jobs;
+    if queue.jobs.lock().unwrap().get(&id).is_some_and(|state| state.cancelled) {
+        result.cancelled = true;
+        return result;
+    }
+
+    let tool_path = format!("binaries/{}.jar", job.tool);
+    if !Path::new(&tool_path).exists() {
+        result.output = format!("{}.jar not found", job.tool);
+        handle.emit("log-error", &result.output).unwrap();
+        return result;
+    }
+
+    let mut child = match Command::new("java")
+        .args(["-jar", &tool_path])
+        .args(&job.args)
+        .envs(job.envs.iter().map(|(key, value)| (key, value)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) => {
+            result.output = format!("Failed to start {}: {}", job.tool, error);
+            return result;
+        }
+    };
+
+    let output = Arc::new(Mutex::new(String::new()));
+    let readers: Vec<_> = [child.stdout.take().map(|stdout| Box::new(stdout) as Box<dyn Read + Send>), child.stderr.take().map(|stderr| Box::new(stderr) as Box<dyn Read + Send>)]
+        .into_iter()
+        .flatten()
+        .map(|stream| {
+            let (handle, output, tool) = (handle.clone(), output.clone(), job.tool.clone());
+            std::thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let line = line.trim().to_string();
+                    handle.emit("job-progress", JobProgress { id, tool: tool.clone(), line: line.clone() }).unwrap();
+                    let mut output = output.lock().unwrap();
+                    output.push_str(&line);
+                    output.push('\n');
+                    // Only the tail is kept, that's where tools print why they failed
+                    if output.len() > MAX_OUTPUT {
+                        let mut cut = output.len() - MAX_OUTPUT;
+                        while !output.is_char_boundary(cut) {
+                            cut += 1;
+                        }
+                        output.drain(..cut);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // Polled so cancel_job can take the lock and kill the child in between
+    let child = Arc::new(Mutex::new(child));
+    if let Some(state) = queue.jobs.lock().unwrap().get_mut(&id) {
+        state.child = Some(child.clone());
+        // cancel_job may have landed before there was a child to kill
+        if state.cancelled {
+            let _ = child.lock().unwrap().kill();
+        }
+    }
+    let status = loop {
+        match child.lock().unwrap().try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) => {}
+            Err(_) => break None,
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    result.cancelled = queue.jobs.lock().unwrap().get(&id).is_some_and(|state| state.cancelled);
+    result.exit_code = status.and_then(|status| status.code());
+    result.success = status.is_some_and(|status| status.success()) && !result.cancelled;
+    result.duration_ms = start.elapsed().as_millis() as u64;
+    result.output = output.lock().unwrap().trim().to_string();
+    result
+}
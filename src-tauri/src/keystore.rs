@@ -0,0 +1,413 @@
+use std::{collections::BTreeMap, fs::File, io::Read, path::{Path, PathBuf}, process::Command};
+
+use aes_gcm::{aead::{Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose, Engine};
+use which::{which, which_in};
+
+/// Environment variables used to hand passwords to keytool and apksigner, so they never show up in argv.
+pub const STORE_PASSWORD_ENV: &str = "TUYU_STORE_PASSWORD";
+pub const KEY_PASSWORD_ENV: &str = "TUYU_KEY_PASSWORD";
+
+/// The debug keystore shipped with Tuyu, used when a project has no signing profile.
+const BUNDLED_KEYSTORE: &str = "binaries/tuyu.keystore";
+const BUNDLED_ALIAS: &str = "tuyu";
+const BUNDLED_PASSWORD: &str = "tuyu123";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeystoreFormat {
+    #[default]
+    Pkcs12,
+    Jks,
+}
+
+impl KeystoreFormat {
+    fn store_type(self) -> &'static str {
+        match self {
+            KeystoreFormat::Pkcs12 => "PKCS12",
+            KeystoreFormat::Jks => "JKS",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            KeystoreFormat::Pkcs12 => "p12",
+            KeystoreFormat::Jks => "jks",
+        }
+    }
+
+    /// JKS files start with `0xfeedfeed`, anything else is treated as PKCS12.
+    pub fn detect(path: &Path) -> Result<KeystoreFormat, String> {
+        let mut magic = [0u8; 4];
+        File::open(path).and_then(|mut file| file.read_exact(&mut magic)).map_err(|e| e.to_string())?;
+        Ok(if magic == [0xfe, 0xed, 0xfe, 0xed] { KeystoreFormat::Jks } else { KeystoreFormat::Pkcs12 })
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct Keystore {
+    pub path: String,
+    pub format: KeystoreFormat,
+    // Obfuscated with the key in `password-obfuscation.key`, absent when the password is prompted for on use
+    #[serde(default)]
+    store_password: Option<String>,
+    #[serde(default)]
+    key_password: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SigningProfile {
+    pub keystore: String,
+    pub alias: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default)]
+#[serde(default)]
+struct KeystoreConfig {
+    keystores: BTreeMap<String, Keystore>,
+    profiles: BTreeMap<String, SigningProfile>, // keyed by project name
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct KeystoreInfo {
+    pub name: String,
+    pub path: String,
+    pub format: KeystoreFormat,
+    pub password_saved: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct KeyAlias {
+    pub alias: String,
+    pub entry_type: String,
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct KeystoreOptions {
+    pub name: String,
+    #[serde(default)]
+    pub format: KeystoreFormat,
+    pub alias: String,
+    pub password: String,
+    pub validity_days: u32,
+    pub distinguished_name: String,
+    #[serde(default)]
+    pub remember_password: bool,
+}
+
+/// Everything apksigner needs to sign with a key, passwords are passed through the environment.
+pub struct SigningKey {
+    pub keystore: String,
+    pub alias: String,
+    pub store_password: String,
+    pub key_password: String,
+}
+
+impl SigningKey {
+    /// apksigner options for this key, `slot` keeps the password variables of multiple signers apart.
+    pub fn args(&self, slot: usize) -> Vec<String> {
+        let (store_env, key_env) = password_envs(slot);
+        vec![
+            "--ks".to_string(), self.keystore.clone(),
+            "--ks-key-alias".to_string(), self.alias.clone(),
+            "--ks-pass".to_string(), format!("env:{}", store_env),
+            "--key-pass".to_string(), format!("env:{}", key_env),
+        ]
+    }
+
+    pub fn envs(&self, slot: usize) -> Vec<(String, String)> {
+        let (store_env, key_env) = password_envs(slot);
+        vec![(store_env, self.store_password.clone()), (key_env, self.key_password.clone())]
+    }
+}
+
+fn password_envs(slot: usize) -> (String, String) {
+    match slot {
+        0 => (STORE_PASSWORD_ENV.to_string(), KEY_PASSWORD_ENV.to_string()),
+        slot => (format!("{}_{}", STORE_PASSWORD_ENV, slot), format!("{}_{}", KEY_PASSWORD_ENV, slot)),
+    }
+}
+
+pub fn get_keytool() -> Option<String> {
+    let java_home = std::env::var("JAVA_HOME").ok().map(|home| Path::new(&home).join("bin"));
+    which("keytool").or_else(|_| which_in("keytool", java_home, std::env::current_dir().unwrap()))
+        .ok()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+fn keytool(args: &[&str], store_password: &str, key_password: Option<&str>) -> Result<String, String> {
+    let keytool = get_keytool().ok_or("keytool not found, install a JDK".to_string())?;
+    // English output so the alias listing can be parsed regardless of the system locale
+    let output = Command::new(keytool)
+        .arg("-J-Duser.language=en")
+        .args(args)
+        .args(["-storepass:env", STORE_PASSWORD_ENV])
+        .env(STORE_PASSWORD_ENV, store_password)
+        .env(KEY_PASSWORD_ENV, key_password.unwrap_or(store_password))
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if output.status.success() {
+        Ok(stdout)
+    } else {
+        let message = format!("{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+        Err(message.lines().map(|line| line.trim()).find(|line| line.starts_with("keytool error")).unwrap_or(message.trim()).to_string())
+    }
+}
+
+/// Parses `keytool -list` output, entries look like `alias, Oct 18, 2026, PrivateKeyEntry,`.
+fn parse_aliases(output: &str) -> Vec<KeyAlias> {
+    let mut aliases: Vec<KeyAlias> = Vec::new();
+    for line in output.lines() {
+        if let Some(fingerprint) = line.strip_prefix("Certificate fingerprint (SHA-256): ") {
+            if let Some(alias) = aliases.last_mut() {
+                alias.fingerprint = Some(fingerprint.trim().replace(':', "").to_lowercase());
+            }
+            continue;
+        }
+
+        let line = line.trim().trim_end_matches(',');
+        let Some((rest, entry_type)) = line.rsplit_once(", ") else {
+            continue;
+        };
+        if !entry_type.ends_with("Entry") {
+            continue;
+        }
+        // The creation date itself contains a comma
+        let alias = rest.rsplitn(3, ", ").nth(2).unwrap_or(rest);
+        aliases.push(KeyAlias { alias: alias.to_string(), entry_type: entry_type.to_string(), fingerprint: None });
+    }
+    aliases
+}
+
+/// Keystore names become file names in the config dir, so anything that could leave it is rejected.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.trim().is_empty()
+        && !name.starts_with('.')
+        && !name.contains("..")
+        && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '));
+    if valid { Ok(()) } else { Err(format!("Invalid keystore name {}, use letters, digits, spaces, '-', '_' or '.'", name)) }
+}
+
+/// Keystores and per project signing profiles, persisted as JSON in the app config dir.
+#[derive(Debug, Default)]
+pub struct KeystoreManager {
+    dir: Option<PathBuf>,
+    config: KeystoreConfig,
+}
+
+impl KeystoreManager {
+    pub fn load(dir: Option<PathBuf>) -> KeystoreManager {
+        let config = dir.as_ref()
+            .and_then(|dir| std::fs::read_to_string(dir.join("keystores.json")).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        KeystoreManager { dir, config }
+    }
+
+    fn dir(&self) -> Result<&PathBuf, String> {
+        self.dir.as_ref().ok_or("No config directory".to_string())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let dir = self.dir()?;
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let data = serde_json::to_string_pretty(&self.config).map_err(|e| e.to_string())?;
+        std::fs::write(dir.join("keystores.json"), data).map_err(|e| e.to_string())
+    }
+
+    /// The key saved passwords are obfuscated with, created on first use and only readable by the user.
+    ///
+    /// It lives next to `keystores.json`, so this only keeps passwords out of plain sight, e.g. when the
+    /// config is shared or backed up on its own. Anyone who can read the config dir can recover them,
+    /// passwords of release keystores should be prompted for instead of saved.
+    fn obfuscation_cipher(&self) -> Result<Aes256Gcm, String> {
+        let path = self.dir()?.join("password-obfuscation.key");
+        if let Ok(key) = std::fs::read(&path) {
+            if key.len() == 32 {
+                return Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+            }
+        }
+
+        let key = Aes256Gcm::generate_key(OsRng);
+        std::fs::create_dir_all(self.dir()?).map_err(|e| e.to_string())?;
+        std::fs::write(&path, key.as_slice()).map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+        }
+        Ok(Aes256Gcm::new(&key))
+    }
+
+    fn obfuscate(&self, secret: &str) -> Result<String, String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut data = nonce.to_vec();
+        data.extend(self.obfuscation_cipher()?.encrypt(&nonce, secret.as_bytes()).map_err(|e| e.to_string())?);
+        Ok(general_purpose::STANDARD.encode(data))
+    }
+
+    fn deobfuscate(&self, secret: &str) -> Result<String, String> {
+        let data = general_purpose::STANDARD.decode(secret).map_err(|e| e.to_string())?;
+        if data.len() < 12 {
+            return Err("Corrupted saved password".to_string());
+        }
+        let plain = self.obfuscation_cipher()?.decrypt(Nonce::from_slice(&data[..12]), &data[12..]).map_err(|_| "Saved password can not be recovered".to_string())?;
+        String::from_utf8(plain).map_err(|e| e.to_string())
+    }
+
+    fn keystore(&self, name: &str) -> Result<&Keystore, String> {
+        self.config.keystores.get(name).ok_or(format!("Keystore {} not found", name))
+    }
+
+    fn info(&self, name: &str, keystore: &Keystore) -> KeystoreInfo {
+        KeystoreInfo {
+            name: name.to_string(),
+            path: keystore.path.clone(),
+            format: keystore.format,
+            password_saved: keystore.store_password.is_some(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<KeystoreInfo> {
+        self.config.keystores.iter().map(|(name, keystore)| self.info(name, keystore)).collect()
+    }
+
+    /// The store password given by the user, or the saved one.
+    fn store_password(&self, keystore: &Keystore, password: Option<String>) -> Result<String, String> {
+        match (password, &keystore.store_password) {
+            (Some(password), _) => Ok(password),
+            (None, Some(saved)) => self.deobfuscate(saved),
+            (None, None) => Err("Keystore password required".to_string()),
+        }
+    }
+
+    fn add(&mut self, name: String, keystore: Keystore, password: &str, remember_password: bool) -> Result<KeystoreInfo, String> {
+        let mut keystore = keystore;
+        if remember_password {
+            keystore.store_password = Some(self.obfuscate(password)?);
+        }
+        let info = self.info(&name, &keystore);
+        self.config.keystores.insert(name, keystore);
+        self.save()?;
+        Ok(info)
+    }
+
+    pub fn generate(&mut self, options: KeystoreOptions) -> Result<KeystoreInfo, String> {
+        check_name(&options.name)?;
+        if self.config.keystores.contains_key(&options.name) {
+            return Err(format!("Keystore {} already exists", options.name));
+        }
+        // keytool refuses shorter store passwords
+        if options.password.len() < 6 {
+            return Err("Password must be at least 6 characters".to_string());
+        }
+
+        let dir = self.dir()?.join("keystores");
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{}.{}", options.name, options.format.extension()));
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()));
+        }
+        let path = path.to_string_lossy().to_string();
+        let validity = options.validity_days.to_string();
+        keytool(&[
+            "-genkeypair", "-keystore", &path, "-storetype", options.format.store_type(),
+            "-alias", &options.alias, "-keyalg", "RSA", "-keysize", "2048", "-validity", &validity,
+            "-dname", &options.distinguished_name, "-keypass:env", KEY_PASSWORD_ENV,
+        ], &options.password, None)?;
+
+        let keystore = Keystore { path, format: options.format, store_password: None, key_password: None };
+        self.add(options.name, keystore, &options.password, options.remember_password)
+    }
+
+    /// Copies an existing keystore into the app config dir, the password is checked by listing its aliases.
+    pub fn import(&mut self, name: String, source: &Path, password: String, remember_password: bool) -> Result<KeystoreInfo, String> {
+        check_name(&name)?;
+        if self.config.keystores.contains_key(&name) {
+            return Err(format!("Keystore {} already exists", name));
+        }
+        let format = KeystoreFormat::detect(source)?;
+        keytool(&["-list", "-keystore", &source.to_string_lossy()], &password, None)?;
+
+        let dir = self.dir()?.join("keystores");
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{}.{}", name, format.extension()));
+        std::fs::copy(source, &path).map_err(|e| e.to_string())?;
+
+        let keystore = Keystore { path: path.to_string_lossy().to_string(), format, store_password: None, key_password: None };
+        self.add(name, keystore, &password, remember_password)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let keystore = self.config.keystores.remove(name).ok_or(format!("Keystore {} not found", name))?;
+        self.config.profiles.retain(|_, profile| profile.keystore != name);
+        // Only delete the copies made by generate and import
+        if Path::new(&keystore.path).starts_with(self.dir()?.join("keystores")) {
+            let _ = std::fs::remove_file(&keystore.path);
+        }
+        self.save()
+    }
+
+    pub fn aliases(&self, name: &str, password: Option<String>) -> Result<Vec<KeyAlias>, String> {
+        let keystore = self.keystore(name)?;
+        let password = self.store_password(keystore, password)?;
+        let output = keytool(&["-list", "-keystore", &keystore.path], &password, None)?;
+        Ok(parse_aliases(&output))
+    }
+
+    /// Saves or forgets the passwords of a keystore, the key password defaults to the store password.
+    pub fn set_password(&mut self, name: &str, password: Option<String>, key_password: Option<String>) -> Result<(), String> {
+        if let Some(password) = &password {
+            keytool(&["-list", "-keystore", &self.keystore(name)?.path], password, None)?;
+        }
+        let store_password = password.as_deref().map(|password| self.obfuscate(password)).transpose()?;
+        let key_password = key_password.as_deref().map(|password| self.obfuscate(password)).transpose()?;
+        let keystore = self.config.keystores.get_mut(name).ok_or(format!("Keystore {} not found", name))?;
+        keystore.store_password = store_password;
+        keystore.key_password = key_password;
+        self.save()
+    }
+
+    pub fn profile(&self, project: &str) -> Option<SigningProfile> {
+        self.config.profiles.get(project).cloned()
+    }
+
+    pub fn set_profile(&mut self, project: String, profile: Option<SigningProfile>) -> Result<(), String> {
+        match profile {
+            Some(profile) => {
+                self.keystore(&profile.keystore)?;
+                self.config.profiles.insert(project, profile);
+            }
+            None => {
+                self.config.profiles.remove(&project);
+            }
+        }
+        self.save()
+    }
+
+    /// The key a project is signed with, falling back to the bundled debug keystore without a profile.
+    pub fn signing_key(&self, project: &str, password: Option<String>) -> Result<SigningKey, String> {
+        let Some(profile) = self.config.profiles.get(project) else {
+            return Ok(SigningKey {
+                keystore: BUNDLED_KEYSTORE.to_string(),
+                alias: BUNDLED_ALIAS.to_string(),
+                store_password: BUNDLED_PASSWORD.to_string(),
+                key_password: BUNDLED_PASSWORD.to_string(),
+            });
+        };
+
+        self.profile_key(profile, password)
+    }
+
+    pub fn profile_key(&self, profile: &SigningProfile, password: Option<String>) -> Result<SigningKey, String> {
+        let keystore = self.keystore(&profile.keystore)?;
+        let store_password = self.store_password(keystore, password)?;
+        let key_password = match &keystore.key_password {
+            Some(saved) => self.deobfuscate(saved)?,
+            None => store_password.clone(),
+        };
+        Ok(SigningKey { keystore: keystore.path.clone(), alias: profile.alias.clone(), store_password, key_password })
+    }
+}
@@ -1,10 +1,29 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, sync::Mutex};
 
 use adb_client::ADBServer;
 use tauri::Manager;
 
+mod apksig;
+mod arsc;
+mod axml;
+mod backup;
+mod broadcast;
+mod bugreport;
+mod capture;
+mod cert;
+mod clipboard;
 mod commands;
+mod icon;
+mod jobs;
+mod keystore;
+mod manifest;
+mod pipeline;
+mod registry;
+mod sampler;
+mod signing;
+mod simulation;
 mod utils;
+mod zipalign;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -14,7 +33,14 @@ pub fn run() {
             let mut adb_server = ADBServer::default();
             adb_server.set_adb_path(utils::get_adb());
             app.manage(commands::AppData { 
-                adb_server: Mutex::new(adb_server)
+                adb_server: Mutex::new(adb_server),
+                samplers: Mutex::new(HashMap::new()),
+                captures: Mutex::new(HashMap::new()),
+                simulations: Mutex::new(HashMap::new()),
+                clipboard_syncs: Mutex::new(HashMap::new()),
+                registry: Mutex::new(registry::DeviceRegistry::load(app.path().app_config_dir().ok().map(|dir| dir.join("devices.json")))),
+                keystores: Mutex::new(keystore::KeystoreManager::load(app.path().app_config_dir().ok())),
+                jobs: jobs::JobQueue::default(),
              });
             Ok(())
         })
@@ -31,6 +57,82 @@ pub fn run() {
             commands::get_list,
             commands::hook_shell,
             commands::pwd,
+            commands::start_activity,
+            commands::get_permissions,
+            commands::grant_permission,
+            commands::revoke_permission,
+            commands::reset_permissions,
+            commands::get_appops,
+            commands::set_appop,
+            commands::reset_appops,
+            commands::list_settings,
+            commands::get_setting,
+            commands::put_setting,
+            commands::delete_setting,
+            commands::get_quick_settings,
+            commands::apply_quick_setting,
+            commands::snapshot_settings,
+            commands::restore_settings,
+            commands::list_processes,
+            commands::kill_process,
+            commands::kill_package,
+            commands::kill_background_processes,
+            commands::start_perf_sampler,
+            commands::stop_perf_sampler,
+            commands::get_perf_samples,
+            commands::export_perf_samples,
+            commands::clear_perf_sampler,
+            commands::dump_ui_hierarchy,
+            commands::input_tap,
+            commands::input_swipe,
+            commands::capture_bugreport,
+            commands::index_bugreport,
+            commands::read_bugreport_entry,
+            commands::start_packet_capture,
+            commands::stop_packet_capture,
+            commands::set_http_proxy,
+            commands::clear_http_proxy,
+            commands::install_ca_certificate,
+            commands::simulate_battery,
+            commands::reset_battery,
+            commands::get_connectivity,
+            commands::set_connectivity,
+            commands::set_mock_location,
+            commands::clear_mock_location,
+            commands::get_simulation_state,
+            commands::reset_simulations,
+            commands::get_device_clipboard,
+            commands::set_device_clipboard,
+            commands::start_clipboard_sync,
+            commands::stop_clipboard_sync,
+            commands::broadcast,
+            commands::get_device_profiles,
+            commands::update_device_profile,
+            commands::remove_device_profile,
+            commands::list_users,
+            commands::list_packages,
+            commands::install_apk,
+            commands::uninstall_package,
+            commands::run_as,
+            commands::backup_app_data,
+            commands::restore_app_data,
+            commands::read_backup_metadata,
+            commands::get_apk_manifest,
+            commands::resolve_resource,
+            commands::analyze_manifest,
+            commands::verify_apk_signature,
+            commands::list_keystores,
+            commands::generate_keystore,
+            commands::import_keystore,
+            commands::remove_keystore,
+            commands::list_keystore_aliases,
+            commands::set_keystore_password,
+            commands::get_signing_profile,
+            commands::set_signing_profile,
+            commands::align_apk,
+            commands::verify_alignment,
+            commands::rebuild_app,
+            commands::cancel_job,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
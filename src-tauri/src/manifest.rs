@@ -0,0 +1,286 @@
+//! Component, permission and security flag analysis of an AndroidManifest.xml.
+
+use crate::{arsc::{ResourceConfig, ResourceTable}, axml::{XmlAttribute, XmlElement}};
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct UsedPermission {
+    pub name: String,
+    pub max_sdk: Option<u32>,
+    pub protection_level: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct DeclaredPermission {
+    pub name: String,
+    pub protection_level: String,
+    pub group: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone, Default)]
+pub struct IntentData {
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub path: Option<String>,
+    pub path_prefix: Option<String>,
+    pub path_pattern: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct IntentFilter {
+    pub actions: Vec<String>,
+    pub categories: Vec<String>,
+    pub data: Vec<IntentData>,
+    pub priority: Option<i32>,
+    pub auto_verify: bool,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct MetaData {
+    pub name: String,
+    pub value: Option<String>,
+    pub resource: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct Component {
+    pub name: String,
+    pub exported: bool,
+    pub exported_explicit: bool,
+    pub enabled: bool,
+    pub permission: Option<String>,
+    pub process: Option<String>,
+    pub target_activity: Option<String>, // activity-alias
+    pub authorities: Option<String>, // providers
+    pub read_permission: Option<String>,
+    pub write_permission: Option<String>,
+    pub grant_uri_permissions: bool,
+    pub intent_filters: Vec<IntentFilter>,
+    pub meta_data: Vec<MetaData>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct ManifestAnalysis {
+    pub package: String,
+    pub version_name: Option<String>,
+    pub version_code: Option<String>,
+    pub min_sdk: Option<u32>,
+    pub target_sdk: Option<u32>,
+    pub shared_user_id: Option<String>,
+    pub debuggable: bool,
+    pub allow_backup: bool,
+    pub uses_cleartext_traffic: bool,
+    pub network_security_config: Option<String>,
+    pub permissions: Vec<UsedPermission>,
+    pub declared_permissions: Vec<DeclaredPermission>,
+    pub activities: Vec<Component>,
+    pub services: Vec<Component>,
+    pub receivers: Vec<Component>,
+    pub providers: Vec<Component>,
+    pub meta_data: Vec<MetaData>,
+}
+
+const PROTECTION_LEVELS: [&str; 4] = ["normal", "dangerous", "signature", "signatureOrSystem"];
+const PROTECTION_FLAGS: [(u32, &str); 12] = [
+    (0x10, "privileged"),
+    (0x20, "development"),
+    (0x40, "appop"),
+    (0x80, "pre23"),
+    (0x100, "installer"),
+    (0x200, "verifier"),
+    (0x400, "preinstalled"),
+    (0x800, "setup"),
+    (0x1000, "instant"),
+    (0x2000, "runtime"),
+    (0x4000, "oem"),
+    (0x8000, "vendorPrivileged"),
+];
+
+/// Formats a protectionLevel, compiled manifests store it as base level plus flag bits.
+fn protection_level(attribute: Option<&XmlAttribute>) -> String {
+    let Some(attribute) = attribute else { return "normal".to_string() };
+    let Some(value) = attribute.as_int() else { return attribute.value.clone() };
+    let mut parts = vec![PROTECTION_LEVELS.get((value & 0xf) as usize).copied().unwrap_or("normal")];
+    parts.extend(PROTECTION_FLAGS.iter().filter(|(flag, _)| value & flag != 0).map(|(_, name)| *name));
+    parts.join("|")
+}
+
+/// Protection levels of commonly requested platform permissions.
+fn platform_protection_level(permission: &str) -> Option<&'static str> {
+    let name = permission.strip_prefix("android.permission.")?;
+    Some(match name {
+        "READ_CALENDAR" | "WRITE_CALENDAR" | "CAMERA" | "READ_CONTACTS" | "WRITE_CONTACTS" | "GET_ACCOUNTS"
+        | "ACCESS_FINE_LOCATION" | "ACCESS_COARSE_LOCATION" | "ACCESS_BACKGROUND_LOCATION" | "ACCESS_MEDIA_LOCATION"
+        | "RECORD_AUDIO" | "READ_PHONE_STATE" | "READ_PHONE_NUMBERS" | "CALL_PHONE" | "ANSWER_PHONE_CALLS"
+        | "READ_CALL_LOG" | "WRITE_CALL_LOG" | "ADD_VOICEMAIL" | "USE_SIP" | "PROCESS_OUTGOING_CALLS" | "ACCEPT_HANDOVER"
+        | "BODY_SENSORS" | "BODY_SENSORS_BACKGROUND" | "ACTIVITY_RECOGNITION"
+        | "SEND_SMS" | "RECEIVE_SMS" | "READ_SMS" | "RECEIVE_WAP_PUSH" | "RECEIVE_MMS"
+        | "READ_EXTERNAL_STORAGE" | "WRITE_EXTERNAL_STORAGE" | "READ_MEDIA_IMAGES" | "READ_MEDIA_VIDEO" | "READ_MEDIA_AUDIO"
+        | "READ_MEDIA_VISUAL_USER_SELECTED" | "BLUETOOTH_SCAN" | "BLUETOOTH_CONNECT" | "BLUETOOTH_ADVERTISE"
+        | "NEARBY_WIFI_DEVICES" | "UWB_RANGING" | "POST_NOTIFICATIONS" => "dangerous",
+        "SYSTEM_ALERT_WINDOW" => "signature|setup|appop|installer|pre23|development",
+        "WRITE_SETTINGS" => "signature|preinstalled|appop|pre23",
+        "REQUEST_INSTALL_PACKAGES" | "PACKAGE_USAGE_STATS" | "MANAGE_EXTERNAL_STORAGE" | "SCHEDULE_EXACT_ALARM" => "signature|appop",
+        "INSTALL_PACKAGES" | "DELETE_PACKAGES" | "READ_LOGS" | "WRITE_SECURE_SETTINGS" | "CHANGE_COMPONENT_ENABLED_STATE" => "signature|privileged",
+        "BIND_ACCESSIBILITY_SERVICE" | "BIND_NOTIFICATION_LISTENER_SERVICE" | "BIND_DEVICE_ADMIN" | "BIND_VPN_SERVICE"
+        | "BIND_INPUT_METHOD" | "BIND_WALLPAPER" | "BIND_JOB_SERVICE" | "BIND_AUTOFILL_SERVICE" => "signature",
+        "INTERNET" | "ACCESS_NETWORK_STATE" | "ACCESS_WIFI_STATE" | "CHANGE_WIFI_STATE" | "CHANGE_NETWORK_STATE" | "WAKE_LOCK"
+        | "VIBRATE" | "RECEIVE_BOOT_COMPLETED" | "FOREGROUND_SERVICE" | "BLUETOOTH" | "BLUETOOTH_ADMIN" | "NFC"
+        | "REQUEST_IGNORE_BATTERY_OPTIMIZATIONS" | "USE_BIOMETRIC" | "USE_FINGERPRINT" | "SET_WALLPAPER" | "MODIFY_AUDIO_SETTINGS"
+        | "QUERY_ALL_PACKAGES" | "USE_FULL_SCREEN_INTENT" | "ACCESS_NOTIFICATION_POLICY" | "REQUEST_DELETE_PACKAGES"
+        | "EXPAND_STATUS_BAR" | "TRANSMIT_IR" | "USE_EXACT_ALARM" | "READ_SYNC_SETTINGS" | "WRITE_SYNC_SETTINGS" => "normal",
+        _ => return None,
+    })
+}
+
+struct Analyzer<'a> {
+    package: String,
+    resources: Option<&'a ResourceTable>,
+}
+
+impl Analyzer<'_> {
+    /// Attribute values, with resource ids of compiled manifests shown as `@type/name`.
+    fn value(&self, element: &XmlElement, name: &str) -> Option<String> {
+        let attribute = element.attribute(name)?;
+        match (attribute.reference(), self.resources) {
+            (Some(id), Some(resources)) => Some(resources.name(id).map(|name| format!("@{}", name)).unwrap_or(attribute.value.clone())),
+            _ => Some(attribute.value.clone()),
+        }
+    }
+
+    /// Component names may be relative to the package.
+    fn class_name(&self, name: &str) -> String {
+        match name.strip_prefix('.') {
+            Some(_) => format!("{}{}", self.package, name),
+            None if !name.contains('.') => format!("{}.{}", self.package, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn flag(&self, element: &XmlElement, name: &str, default: bool) -> bool {
+        element.attribute(name).and_then(|attribute| attribute.as_bool()).unwrap_or(default)
+    }
+
+    fn meta_data(&self, element: &XmlElement) -> Vec<MetaData> {
+        element.children_named("meta-data").filter_map(|meta_data| {
+            let value = meta_data.attribute("value").map(|value| match (value.reference(), self.resources) {
+                // Referenced strings are resolved, other resources keep their name
+                (Some(id), Some(resources)) => resources.resolve_string(id, &ResourceConfig::default()).unwrap_or(value.value.clone()),
+                _ => value.value.clone(),
+            });
+            Some(MetaData {
+                name: meta_data.attribute_value("name")?.to_string(),
+                value,
+                resource: self.value(meta_data, "resource"),
+            })
+        }).collect()
+    }
+
+    fn intent_filters(&self, element: &XmlElement) -> Vec<IntentFilter> {
+        element.children_named("intent-filter").map(|filter| {
+            let names = |tag: &'static str| filter.children_named(tag).filter_map(|child| child.attribute_value("name").map(|name| name.to_string())).collect();
+            IntentFilter {
+                actions: names("action"),
+                categories: names("category"),
+                data: filter.children_named("data").map(|data| IntentData {
+                    scheme: self.value(data, "scheme"),
+                    host: self.value(data, "host"),
+                    port: self.value(data, "port"),
+                    path: self.value(data, "path"),
+                    path_prefix: self.value(data, "pathPrefix"),
+                    path_pattern: self.value(data, "pathPattern"),
+                    mime_type: self.value(data, "mimeType"),
+                }).collect(),
+                priority: filter.attribute("priority").and_then(|priority| priority.as_int()).map(|priority| priority as i32),
+                auto_verify: self.flag(filter, "autoVerify", false),
+            }
+        }).collect()
+    }
+
+    fn component(&self, element: &XmlElement, default_exported: bool) -> Option<Component> {
+        let intent_filters = self.intent_filters(element);
+        let exported = element.attribute("exported").and_then(|exported| exported.as_bool());
+        Some(Component {
+            name: self.class_name(element.attribute_value("name")?),
+            // Without the attribute, components with intent filters are exported
+            exported: exported.unwrap_or(default_exported || !intent_filters.is_empty()),
+            exported_explicit: exported.is_some(),
+            enabled: self.flag(element, "enabled", true),
+            permission: self.value(element, "permission"),
+            process: self.value(element, "process"),
+            target_activity: element.attribute_value("targetActivity").map(|name| self.class_name(name)),
+            authorities: self.value(element, "authorities"),
+            read_permission: self.value(element, "readPermission"),
+            write_permission: self.value(element, "writePermission"),
+            grant_uri_permissions: self.flag(element, "grantUriPermissions", false),
+            intent_filters,
+            meta_data: self.meta_data(element),
+        })
+    }
+}
+
+/// Analyzes a decoded manifest, `resources` resolves references of compiled manifests.
+pub fn analyze(manifest: &XmlElement, resources: Option<&ResourceTable>) -> Option<ManifestAnalysis> {
+    let analyzer = Analyzer { package: manifest.attribute_value("package")?.to_string(), resources };
+    let uses_sdk = manifest.child("uses-sdk");
+    let sdk = |name: &str| uses_sdk.and_then(|uses_sdk| uses_sdk.attribute(name)).and_then(|sdk| sdk.as_int());
+    let min_sdk = sdk("minSdkVersion");
+    let target_sdk = sdk("targetSdkVersion").or(min_sdk);
+    let empty = XmlElement::default();
+    let application = manifest.child("application").unwrap_or(&empty);
+
+    let permissions = manifest.children.iter()
+        .filter(|child| child.name == "uses-permission" || child.name == "uses-permission-sdk-23")
+        .filter_map(|permission| {
+            let name = permission.attribute_value("name")?.to_string();
+            // Permissions the app declares itself carry their own protection level
+            let protection_level = manifest.children_named("permission")
+                .find(|declared| declared.attribute_value("name") == Some(&name))
+                .map(|declared| protection_level(declared.attribute("protectionLevel")))
+                .or(platform_protection_level(&name).map(|level| level.to_string()));
+            Some(UsedPermission {
+                max_sdk: permission.attribute("maxSdkVersion").and_then(|max_sdk| max_sdk.as_int()),
+                name,
+                protection_level,
+            })
+        })
+        .collect();
+
+    let declared_permissions = manifest.children_named("permission")
+        .filter_map(|permission| Some(DeclaredPermission {
+            name: permission.attribute_value("name")?.to_string(),
+            protection_level: protection_level(permission.attribute("protectionLevel")),
+            group: analyzer.value(permission, "permissionGroup"),
+        }))
+        .collect();
+
+    let components = |tags: &[&str], default_exported: bool| -> Vec<Component> {
+        application.children.iter()
+            .filter(|child| tags.contains(&child.name.as_str()))
+            .filter_map(|child| analyzer.component(child, default_exported))
+            .collect()
+    };
+
+    Some(ManifestAnalysis {
+        version_name: analyzer.value(manifest, "versionName"),
+        version_code: analyzer.value(manifest, "versionCode"),
+        min_sdk,
+        target_sdk,
+        shared_user_id: analyzer.value(manifest, "sharedUserId"),
+        debuggable: analyzer.flag(application, "debuggable", false),
+        allow_backup: analyzer.flag(application, "allowBackup", true),
+        // Cleartext traffic is blocked by default from Android 9
+        uses_cleartext_traffic: analyzer.flag(application, "usesCleartextTraffic", target_sdk.unwrap_or(1) < 28),
+        network_security_config: analyzer.value(application, "networkSecurityConfig"),
+        permissions,
+        declared_permissions,
+        activities: components(&["activity", "activity-alias"], false),
+        services: components(&["service"], false),
+        receivers: components(&["receiver"], false),
+        // Providers were exported by default before Android 4.2
+        providers: components(&["provider"], target_sdk.unwrap_or(1) < 17),
+        meta_data: analyzer.meta_data(application),
+        package: analyzer.package,
+    })
+}
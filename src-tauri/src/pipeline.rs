@@ -0,0 +1,148 @@
+use std::{path::Path, time::Instant};
+
+use tauri::{AppHandle, Emitter};
+
+use crate::{commands, jobs, keystore::SigningKey, signing::{self, SignResult, SigningOptions}, utils, zipalign};
+
+#[derive(Debug, serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PipelineOptions {
+    pub signing: SigningOptions,
+    pub page_size: Option<u32>, // KiB, native libraries are page aligned to it, v2+ signing only keeps 4 KiB
+    pub install_device: Option<String>,
+    pub user_id: Option<u32>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct PipelineStep {
+    pub name: String,   // build, align, sign, verify, install
+    pub status: String, // pending, running, success, failed, skipped
+    pub duration_ms: Option<u64>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct PipelineProgress {
+    pub project: String,
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct PipelineResult {
+    pub project: String,
+    pub output_path: Option<String>,
+    pub steps: Vec<PipelineStep>,
+    pub signature: Option<SignResult>,
+    pub error: Option<String>,
+}
+
+struct Pipeline {
+    handle: AppHandle,
+    project: String,
+    steps: Vec<PipelineStep>,
+}
+
+impl Pipeline {
+    fn emit(&self) {
+        let progress = PipelineProgress { project: self.project.clone(), steps: self.steps.clone() };
+        self.handle.emit("pipeline-progress", progress).unwrap();
+    }
+
+    /// Runs the next pending step, every step after a failed one is marked as skipped.
+    fn run<F: FnOnce() -> Result<Option<String>, String>>(&mut self, index: usize, step: F) -> Result<(), String> {
+        self.steps[index].status = "running".to_string();
+        self.emit();
+
+        let start = Instant::now();
+        let result = step();
+        let step = &mut self.steps[index];
+        step.duration_ms = Some(start.elapsed().as_millis() as u64);
+        match &result {
+            Ok(message) => {
+                step.status = "success".to_string();
+                step.message = message.clone();
+            }
+            Err(error) => {
+                step.status = "failed".to_string();
+                step.message = Some(error.clone());
+                for step in self.steps.iter_mut().skip(index + 1) {
+                    step.status = "skipped".to_string();
+                }
+            }
+        }
+        self.emit();
+        result.map(|_| ())
+    }
+}
+
+/// Builds a decompiled app into compiled/<project>.apk, aligns and signs it in the order its schemes allow and verifies it,
+/// then installs it when a device is given.
+pub fn run(handle: AppHandle, app_path: String, project: String, key: SigningKey, previous: Option<SigningKey>, options: PipelineOptions) {
+    std::thread::spawn(move || {
+        // v1 signatures survive zipalign, so the signed APK is aligned and keeps the full page size. v2+ signatures
+        // cover the whole file and apksigner re-pads the native libraries it moves to 4 KiB pages, which is all
+        // that can be verified then
+        let v1_only = options.signing.v1_only();
+        let page_size = if v1_only { options.page_size.or(Some(16)) } else { Some(4) };
+        let mut names = if v1_only { vec!["build", "sign", "align", "verify"] } else { vec!["build", "align", "sign", "verify"] };
+        if options.install_device.is_some() {
+            names.push("install");
+        }
+        let steps = names.iter()
+            .map(|name| PipelineStep { name: name.to_string(), status: "pending".to_string(), duration_ms: None, message: None })
+            .collect();
+        let mut pipeline = Pipeline { handle: handle.clone(), project: project.clone(), steps };
+        pipeline.emit();
+
+        let output_path = format!("compiled/{}.apk", project);
+        let signing_options = SigningOptions { align: false, ..options.signing.clone() };
+        let mut signature = SignResult { apk_path: output_path.clone(), ..Default::default() };
+
+        let mut result = Ok(());
+        for (index, name) in names.iter().enumerate() {
+            result = pipeline.run(index, || match *name {
+                "build" => jobs::run_java_tool_blocking(&handle, "apktool", &["b", &app_path, "-o", &output_path], &[]).map(|_| None),
+                "align" => {
+                    let path = signature.output_path.clone().unwrap_or(output_path.clone());
+                    zipalign::align(Path::new(&path), Path::new(&path), options.page_size.or(Some(16)))?;
+                    signature.aligned = true;
+                    Ok(None)
+                }
+                "sign" => signing::apply_signature(&handle, &output_path, &key, previous.as_ref(), &signing_options, &mut signature).map(|_| None),
+                "verify" => {
+                    signing::check_signature(&mut signature)?;
+                    let signed_path = signature.output_path.clone().ok_or("APK was not signed".to_string())?;
+                    let alignment = zipalign::verify(Path::new(&signed_path), page_size)?;
+                    if !alignment.aligned {
+                        return Err(format!("{} entries are not aligned", alignment.misaligned.len()));
+                    }
+                    Ok(Some(format!("Signed with {}", signature.schemes.join(", "))))
+                }
+                "install" => {
+                    let device_id = options.install_device.as_ref().ok_or("No device to install on")?;
+                    let signed_path = signature.output_path.clone().unwrap_or(output_path.clone());
+                    let mut device = commands::try_get_device(&handle, device_id)?;
+                    utils::install_staged_apk(&mut device, &signed_path, options.user_id).map(|_| Some(format!("Installed on {}", device_id)))
+                }
+                _ => unreachable!(),
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+
+        match &result {
+            Ok(()) => handle.emit("log", format!("{} rebuilt successfully", project)).unwrap(),
+            Err(error) => handle.emit("log", format!("Failed to rebuild {}: {}", project, error)).unwrap(),
+        }
+        let result = PipelineResult {
+            project,
+            // The signing options may write the signed APK somewhere else than the build output
+            output_path: result.is_ok().then(|| signature.output_path.clone().unwrap_or(output_path)),
+            steps: pipeline.steps,
+            signature: signature.output_path.is_some().then_some(signature),
+            error: result.err(),
+        };
+        handle.emit("pipeline-finished", result).unwrap();
+    });
+}
@@ -0,0 +1,47 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct DeviceProfile {
+    pub alias: Option<String>,
+    pub tags: Vec<String>,
+    pub notes: String,
+    pub last_seen: Option<u64>, // seconds since epoch
+    pub last_wifi_endpoint: Option<String>,
+}
+
+/// User data about devices, persisted as JSON in the app config dir and keyed by serial number.
+#[derive(Debug, Default)]
+pub struct DeviceRegistry {
+    path: Option<PathBuf>,
+    pub devices: BTreeMap<String, DeviceProfile>,
+}
+
+impl DeviceRegistry {
+    pub fn load(path: Option<PathBuf>) -> DeviceRegistry {
+        let devices = path.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        DeviceRegistry { path, devices }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let data = serde_json::to_string_pretty(&self.devices).map_err(|e| e.to_string())?;
+        std::fs::write(path, data).map_err(|e| e.to_string())
+    }
+
+    /// Records that a device was seen, wireless devices are identified by their `ip:port` endpoint.
+    pub fn touch(&mut self, serial: &str, id: &str, now: u64) -> DeviceProfile {
+        let profile = self.devices.entry(serial.to_string()).or_default();
+        profile.last_seen = Some(now);
+        if id != serial && id.contains(':') {
+            profile.last_wifi_endpoint = Some(id.to_string());
+        }
+        profile.clone()
+    }
+}
@@ -0,0 +1,306 @@
+use std::{io::Write, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use adb_client::{ADBDeviceExt, ADBServerDevice};
+use tauri::{AppHandle, Emitter};
+
+use crate::utils::shell_quote;
+
+#[derive(Debug, serde::Serialize, Clone, Default)]
+pub struct PerfSample {
+    pub session_id: String,
+    pub timestamp: u64, // ms since epoch
+    pub pid: Option<u32>,
+    pub cpu: Option<f32>, // % of one core, like top
+    pub total_pss: Option<u64>, // KiB
+    pub java_heap: Option<u64>, // KiB
+    pub native_heap: Option<u64>, // KiB
+    pub fps: Option<f32>,
+    pub janky_frames: Option<u64>,
+    pub max_frame_time: Option<f32>, // ms
+    pub rx_rate: Option<f64>, // bytes/s
+    pub tx_rate: Option<f64>, // bytes/s
+}
+
+pub struct PerfSampler {
+    pub stop: Arc<AtomicBool>,
+    pub samples: Arc<Mutex<Vec<PerfSample>>>,
+}
+
+#[derive(Default)]
+struct Previous {
+    pid: Option<u32>,
+    proc_jiffies: u64,
+    total_jiffies: u64,
+    frames: Option<u64>,
+    janky: Option<u64>,
+    last_frame: u64,
+    net: Option<(u64, u64)>,
+    at: Option<Instant>,
+}
+
+pub fn start(handle: AppHandle, mut device: ADBServerDevice, session_id: String, package: String, uid: Option<u32>, interval: Duration) -> PerfSampler {
+    let stop = Arc::new(AtomicBool::new(false));
+    let samples = Arc::new(Mutex::new(Vec::new()));
+
+    let stop_clone = stop.clone();
+    let samples_clone = samples.clone();
+    thread::spawn(move || {
+        let mut previous = Previous::default();
+        // xt_qtaguid is gone since Android 10, fall back to netstats there
+        let qtaguid = run(&mut device, "test -r /proc/net/xt_qtaguid/stats && echo yes").trim() == "yes";
+
+        while !stop_clone.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            let sample = collect(&mut device, &session_id, &package, uid, qtaguid, &mut previous);
+            handle.emit("perf-sample", sample.clone()).unwrap();
+            samples_clone.lock().unwrap().push(sample);
+
+            let elapsed = started.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+    });
+
+    PerfSampler { stop, samples }
+}
+
+fn run(device: &mut ADBServerDevice, script: &str) -> String {
+    let mut output = Vec::new();
+    let _ = device.shell_command(&[script], &mut output);
+    String::from_utf8_lossy(&output).to_string()
+}
+
+/// Splits the combined script output on the `@@TUYU:NAME@@` markers.
+fn section<'a>(output: &'a str, name: &str) -> &'a str {
+    let marker = format!("@@TUYU:{}@@", name);
+    let Some(start) = output.find(&marker) else { return "" };
+    let rest = &output[start + marker.len()..];
+    match rest.find("@@TUYU:") {
+        Some(end) => &rest[..end],
+        None => rest,
+    }
+}
+
+fn collect(device: &mut ADBServerDevice, session_id: &str, package: &str, uid: Option<u32>, qtaguid: bool, previous: &mut Previous) -> PerfSample {
+    let mut sample = PerfSample {
+        session_id: session_id.to_string(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+        ..Default::default()
+    };
+
+    sample.pid = run(device, &format!("pidof {}", shell_quote(package))).split_whitespace().next().and_then(|pid| pid.parse().ok());
+    let Some(pid) = sample.pid else {
+        *previous = Previous::default();
+        return sample;
+    };
+    if previous.pid != Some(pid) {
+        // The app restarted, counters from the old process are meaningless
+        *previous = Previous { pid: Some(pid), ..Default::default() };
+    }
+
+    let package = shell_quote(package);
+    let net = match (uid, qtaguid) {
+        (Some(uid), true) => format!("grep ' 0x0 {} ' /proc/net/xt_qtaguid/stats", uid),
+        (Some(_), false) => "dumpsys netstats detail".to_string(),
+        (None, _) => String::new(),
+    };
+    let script = format!(
+        "echo @@TUYU:STAT@@; (grep ^cpu /proc/stat 2>/dev/null || echo TOP $(top -b -n 1 -p {pid} -o %CPU | tail -n 1)); \
+         echo @@TUYU:PSTAT@@; cat /proc/{pid}/stat; \
+         echo @@TUYU:MEM@@; dumpsys meminfo {package}; \
+         echo @@TUYU:GFX@@; dumpsys gfxinfo {package} framestats; \
+         echo @@TUYU:NET@@; {net}"
+    );
+    let output = run(device, &script);
+    let now = Instant::now();
+    let elapsed = previous.at.map(|at| now.duration_since(at).as_secs_f64());
+    previous.at = Some(now);
+
+    sample.cpu = parse_cpu(section(&output, "STAT"), section(&output, "PSTAT"), previous);
+
+    let (total_pss, java_heap, native_heap) = parse_meminfo(section(&output, "MEM"));
+    sample.total_pss = total_pss;
+    sample.java_heap = java_heap;
+    sample.native_heap = native_heap;
+
+    let gfx = parse_gfxinfo(section(&output, "GFX"), previous.last_frame);
+    if let (Some(frames), Some(last), Some(elapsed)) = (gfx.total_frames, previous.frames, elapsed) {
+        sample.fps = Some(frames.saturating_sub(last) as f32 / elapsed as f32);
+    }
+    if let (Some(janky), Some(last)) = (gfx.janky_frames, previous.janky) {
+        sample.janky_frames = Some(janky.saturating_sub(last));
+    }
+    sample.max_frame_time = gfx.max_frame_time;
+    previous.frames = gfx.total_frames;
+    previous.janky = gfx.janky_frames;
+    previous.last_frame = gfx.last_frame.max(previous.last_frame);
+
+    if let Some(uid) = uid {
+        let net = if qtaguid { parse_qtaguid(section(&output, "NET")) } else { parse_netstats(section(&output, "NET"), uid) };
+        if let (Some((rx, tx)), Some((last_rx, last_tx)), Some(elapsed)) = (net, previous.net, elapsed) {
+            sample.rx_rate = Some(rx.saturating_sub(last_rx) as f64 / elapsed);
+            sample.tx_rate = Some(tx.saturating_sub(last_tx) as f64 / elapsed);
+        }
+        previous.net = net;
+    }
+
+    sample
+}
+
+fn parse_cpu(stat: &str, process_stat: &str, previous: &mut Previous) -> Option<f32> {
+    if let Some(top) = stat.trim().strip_prefix("TOP") {
+        return top.trim().parse().ok();
+    }
+
+    let total_line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let total = total_line.split_whitespace().skip(1).take(8).filter_map(|v| v.parse::<u64>().ok()).sum::<u64>();
+    let cores = stat.lines().filter(|line| line.starts_with("cpu") && line.as_bytes().get(3).is_some_and(|c| c.is_ascii_digit())).count().max(1);
+
+    // The process name can contain spaces, so fields are counted after the closing parenthesis
+    let fields: Vec<&str> = process_stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime = fields.get(11)?.parse::<u64>().ok()?;
+    let stime = fields.get(12)?.parse::<u64>().ok()?;
+    let process = utime + stime;
+
+    let cpu = if previous.total_jiffies > 0 && total > previous.total_jiffies {
+        Some(process.saturating_sub(previous.proc_jiffies) as f32 / (total - previous.total_jiffies) as f32 * cores as f32 * 100.0)
+    } else {
+        None
+    };
+    previous.proc_jiffies = process;
+    previous.total_jiffies = total;
+    cpu
+}
+
+fn first_number(text: &str) -> Option<u64> {
+    text.split_whitespace().find_map(|part| part.parse().ok())
+}
+
+fn parse_meminfo(output: &str) -> (Option<u64>, Option<u64>, Option<u64>) {
+    let mut total_pss = None;
+    let mut java_heap = None;
+    let mut native_heap = None;
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("TOTAL PSS:") {
+            total_pss = first_number(rest);
+        } else if total_pss.is_none() && line.starts_with("TOTAL ") {
+            total_pss = first_number(line);
+        } else if let Some(rest) = line.strip_prefix("Java Heap:") {
+            java_heap = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Native Heap:") {
+            native_heap = first_number(rest);
+        }
+    }
+
+    (total_pss, java_heap, native_heap)
+}
+
+#[derive(Default)]
+struct GfxInfo {
+    total_frames: Option<u64>,
+    janky_frames: Option<u64>,
+    max_frame_time: Option<f32>,
+    last_frame: u64,
+}
+
+fn parse_gfxinfo(output: &str, since: u64) -> GfxInfo {
+    let mut info = GfxInfo::default();
+    let mut columns: Vec<&str> = Vec::new();
+    let mut in_profile = false;
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Total frames rendered:") {
+            info.total_frames = first_number(rest);
+        } else if let Some(rest) = line.strip_prefix("Janky frames:") {
+            info.janky_frames = first_number(rest);
+        } else if line == "---PROFILEDATA---" {
+            in_profile = !in_profile;
+            columns.clear();
+        } else if in_profile && line.starts_with("Flags,") {
+            columns = line.split(',').collect();
+        } else if in_profile && !columns.is_empty() {
+            let values: Vec<&str> = line.split(',').collect();
+            let column = |name: &str| columns.iter().position(|c| *c == name).and_then(|i| values.get(i)).and_then(|v| v.parse::<u64>().ok());
+            let (Some(0), Some(intended), Some(completed)) = (column("Flags"), column("IntendedVsync"), column("FrameCompleted")) else { continue };
+            if completed <= since {
+                continue;
+            }
+            let frame_time = completed.saturating_sub(intended) as f32 / 1_000_000.0;
+            info.max_frame_time = Some(info.max_frame_time.map_or(frame_time, |max| max.max(frame_time)));
+            info.last_frame = info.last_frame.max(completed);
+        }
+    }
+
+    info
+}
+
+/// Sums rx/tx bytes of the untagged `xt_qtaguid` rows for the uid.
+fn parse_qtaguid(output: &str) -> Option<(u64, u64)> {
+    let mut totals = None;
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (Some(rx), Some(tx)) = (parts.get(5).and_then(|v| v.parse::<u64>().ok()), parts.get(7).and_then(|v| v.parse::<u64>().ok())) else { continue };
+        let (total_rx, total_tx) = totals.get_or_insert((0, 0));
+        *total_rx += rx;
+        *total_tx += tx;
+    }
+    totals
+}
+
+/// Sums the `rb=`/`tb=` buckets of the untagged `dumpsys netstats detail` uid stats.
+fn parse_netstats(output: &str, uid: u32) -> Option<(u64, u64)> {
+    let mut totals = None;
+    let mut matching = false;
+    let mut in_uid_stats = false;
+    let ident = format!("uid={}", uid);
+
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("Dev stats:") || line.starts_with("Xt stats:") || line.starts_with("UID tag stats:") {
+            in_uid_stats = false;
+        } else if line.starts_with("UID stats:") {
+            in_uid_stats = true;
+        } else if in_uid_stats && line.contains(" uid=") {
+            matching = line.split_whitespace().any(|part| part == ident) && line.contains("tag=0x0");
+        } else if in_uid_stats && matching && line.starts_with("st=") {
+            let value = |key: &str| line.split_whitespace().find_map(|part| part.strip_prefix(key)).and_then(|v| v.parse::<u64>().ok());
+            let (total_rx, total_tx) = totals.get_or_insert((0, 0));
+            *total_rx += value("rb=").unwrap_or(0);
+            *total_tx += value("tb=").unwrap_or(0);
+        }
+    }
+
+    totals
+}
+
+pub fn write_csv(samples: &[PerfSample], path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    writeln!(file, "timestamp,pid,cpu,total_pss_kb,java_heap_kb,native_heap_kb,fps,janky_frames,max_frame_time_ms,rx_bytes_per_sec,tx_bytes_per_sec")?;
+
+    fn cell<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+    }
+    for sample in samples {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            sample.timestamp,
+            cell(&sample.pid),
+            cell(&sample.cpu),
+            cell(&sample.total_pss),
+            cell(&sample.java_heap),
+            cell(&sample.native_heap),
+            cell(&sample.fps),
+            cell(&sample.janky_frames),
+            cell(&sample.max_frame_time),
+            cell(&sample.rx_rate),
+            cell(&sample.tx_rate),
+        )?;
+    }
+
+    Ok(())
+}
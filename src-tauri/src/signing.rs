@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use tauri::{AppHandle, Emitter};
+
+use crate::{apksig, jobs, keystore::{SigningKey, SigningProfile}, zipalign};
+
+/// Key rotation with a lineage made by `apksigner rotate`, the previous key still signs v1/v2 and older platforms.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct LineageOptions {
+    pub path: String,
+    pub previous: SigningProfile,
+    pub previous_password: Option<String>,
+    pub rotation_min_sdk_version: Option<u32>,
+}
+
+/// apksigner options, schemes left unset are enabled based on the min SDK like apksigner does.
+#[derive(Debug, serde::Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SigningOptions {
+    pub v1: Option<bool>,
+    pub v2: Option<bool>,
+    pub v3: Option<bool>,
+    pub v4: Option<bool>,
+    pub min_sdk_version: Option<u32>,
+    pub v4_signature_file: Option<String>,
+    pub lineage: Option<LineageOptions>,
+    pub align: bool,
+    pub output_path: Option<String>, // signs in place when unset
+}
+
+#[derive(Debug, serde::Serialize, Clone, Default)]
+pub struct SignResult {
+    pub apk_path: String,
+    pub output_path: Option<String>,
+    pub v4_signature_file: Option<String>,
+    pub aligned: bool,
+    pub schemes: Vec<String>,
+    pub verified: bool,
+    pub error: Option<String>,
+}
+
+impl SigningOptions {
+    /// zipalign may only run after signing when there are no v2+ signatures, they cover the whole file.
+    pub fn v1_only(&self) -> bool {
+        self.v2 == Some(false) && self.v3 == Some(false)
+    }
+}
+
+/// Runs apksigner, aligning as well when asked to. The signed APK is not verified yet, see `check_signature`.
+pub fn apply_signature(handle: &AppHandle, apk_path: &str, key: &SigningKey, previous: Option<&SigningKey>, options: &SigningOptions, result: &mut SignResult) -> Result<(), String> {
+    let output_path = options.output_path.clone().unwrap_or(apk_path.to_string());
+    // apksigner re-pads native libraries to 4 KiB pages whenever it moves an entry, so 16 KiB alignment
+    // is only guaranteed when aligning the signed APK, which v2+ signatures don't allow
+    let align_before = options.align && !options.v1_only();
+    let mut input = apk_path.to_string();
+    if align_before {
+        input = format!("{}.aligned", output_path);
+        // 16 KiB pages also satisfy 4 KiB devices, Android 15 needs them for 16 KiB page size devices
+        zipalign::align(Path::new(apk_path), Path::new(&input), Some(16))?;
+    }
+
+    let mut args = vec!["sign".to_string()];
+    for (scheme, enabled) in [("v1", options.v1), ("v2", options.v2), ("v3", options.v3), ("v4", options.v4)] {
+        if let Some(enabled) = enabled {
+            args.extend([format!("--{}-signing-enabled", scheme), enabled.to_string()]);
+        }
+    }
+    if let Some(min_sdk_version) = options.min_sdk_version {
+        args.extend(["--min-sdk-version".to_string(), min_sdk_version.to_string()]);
+    }
+
+    let mut envs = key.envs(0);
+    match (previous, &options.lineage) {
+        (Some(previous), Some(lineage)) => {
+            args.extend(previous.args(1));
+            args.push("--next-signer".to_string());
+            args.extend(key.args(0));
+            args.extend(["--lineage".to_string(), lineage.path.clone()]);
+            if let Some(rotation_min_sdk_version) = lineage.rotation_min_sdk_version {
+                args.extend(["--rotation-min-sdk-version".to_string(), rotation_min_sdk_version.to_string()]);
+            }
+            envs.extend(previous.envs(1));
+        }
+        _ => args.extend(key.args(0)),
+    }
+    args.extend(["--out".to_string(), output_path.clone(), input.clone()]);
+
+    // apksigner writes the v4 signature next to the output, a stale one would be reported as applied
+    let idsig = format!("{}.idsig", output_path);
+    let _ = std::fs::remove_file(&idsig);
+
+    let signed = jobs::run_java_tool_blocking(handle, "apksigner", &args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>(), &envs);
+    if align_before {
+        let _ = std::fs::remove_file(&input);
+    }
+    signed?;
+    result.output_path = Some(output_path.clone());
+    if options.align && !align_before {
+        zipalign::align(Path::new(&output_path), Path::new(&output_path), Some(16))?;
+    }
+    result.aligned = options.align;
+
+    if Path::new(&idsig).exists() {
+        let v4_signature_file = match &options.v4_signature_file {
+            Some(path) => {
+                std::fs::rename(&idsig, path).map_err(|e| e.to_string())?;
+                path.clone()
+            }
+            None => idsig,
+        };
+        result.v4_signature_file = Some(v4_signature_file);
+    }
+
+    Ok(())
+}
+
+/// Verifies the signed APK with the native verifier and records the schemes it carries.
+pub fn check_signature(result: &mut SignResult) -> Result<(), String> {
+    let output_path = result.output_path.clone().ok_or("APK was not signed".to_string())?;
+    let report = apksig::verify(&std::fs::read(&output_path).map_err(|e| e.to_string())?)?;
+    result.schemes = report.schemes.iter().map(|scheme| scheme.scheme.clone()).collect();
+    if result.v4_signature_file.is_some() {
+        result.schemes.push("v4".to_string());
+    }
+    result.verified = report.verified;
+    if !report.verified {
+        let errors: Vec<String> = report.schemes.iter()
+            .flat_map(|scheme| scheme.errors.iter().chain(scheme.signers.iter().flat_map(|signer| signer.errors.iter())))
+            .chain(report.mismatches.iter())
+            .cloned()
+            .collect();
+        return Err(format!("Signed APK does not verify: {}", errors.join(", ")));
+    }
+    Ok(())
+}
+
+fn sign_blocking(handle: &AppHandle, apk_path: &str, key: &SigningKey, previous: Option<&SigningKey>, options: &SigningOptions, result: &mut SignResult) -> Result<(), String> {
+    apply_signature(handle, apk_path, key, previous, options, result)?;
+    check_signature(result)
+}
+
+pub fn sign(handle: AppHandle, apk_path: String, key: SigningKey, previous: Option<SigningKey>, options: SigningOptions) {
+    std::thread::spawn(move || {
+        let mut result = SignResult { apk_path: apk_path.clone(), ..Default::default() };
+        match sign_blocking(&handle, &apk_path, &key, previous.as_ref(), &options, &mut result) {
+            Ok(()) => handle.emit("log", "App signed successfully").unwrap(),
+            Err(error) => {
+                handle.emit("log", format!("Failed to sign app: {}", error)).unwrap();
+                result.error = Some(error);
+            }
+        }
+        handle.emit("sign-finished", result).unwrap();
+    });
+}
@@ -0,0 +1,131 @@
+#[derive(Debug, serde::Deserialize, Default)]
+#[serde(default)]
+pub struct BatterySimulation {
+    pub level: Option<u8>,
+    pub status: Option<BatteryStatus>,
+    pub ac: Option<bool>,
+    pub usb: Option<bool>,
+    pub wireless: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BatteryStatus {
+    Unknown,
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+}
+
+impl BatteryStatus {
+    /// Values of `BatteryManager.BATTERY_STATUS_*`.
+    fn code(&self) -> u8 {
+        match self {
+            BatteryStatus::Unknown => 1,
+            BatteryStatus::Charging => 2,
+            BatteryStatus::Discharging => 3,
+            BatteryStatus::NotCharging => 4,
+            BatteryStatus::Full => 5,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+pub struct Connectivity {
+    pub wifi: Option<bool>,
+    pub data: Option<bool>,
+    pub airplane: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MockLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy: Option<f32>,
+}
+
+/// What was changed on a device, so "reset simulations" only undoes our own changes.
+#[derive(Debug, serde::Serialize, Default, Clone)]
+pub struct SimulationState {
+    pub battery: bool,
+    pub original_connectivity: Option<Connectivity>,
+    pub mock_location: bool,
+}
+
+pub const MOCK_PROVIDERS: [&str; 2] = ["gps", "network"];
+
+pub fn battery_commands(simulation: &BatterySimulation) -> Vec<String> {
+    let flag = |enabled: bool| if enabled { 1 } else { 0 };
+    let mut commands = Vec::new();
+    if let Some(level) = simulation.level {
+        commands.push(format!("dumpsys battery set level {}", level.min(100)));
+    }
+    if let Some(status) = simulation.status {
+        commands.push(format!("dumpsys battery set status {}", status.code()));
+    }
+    if let Some(ac) = simulation.ac {
+        commands.push(format!("dumpsys battery set ac {}", flag(ac)));
+    }
+    if let Some(usb) = simulation.usb {
+        commands.push(format!("dumpsys battery set usb {}", flag(usb)));
+    }
+    if let Some(wireless) = simulation.wireless {
+        commands.push(format!("dumpsys battery set wireless {}", flag(wireless)));
+    }
+    commands
+}
+
+pub fn connectivity_commands(connectivity: &Connectivity) -> Vec<String> {
+    let toggle = |enabled: bool| if enabled { "enable" } else { "disable" };
+    let mut commands = Vec::new();
+    if let Some(airplane) = connectivity.airplane {
+        // cmd connectivity only knows airplane-mode since Android 11, older releases need the setting and broadcast
+        commands.push(format!(
+            "cmd connectivity airplane-mode {} 2>/dev/null || (settings put global airplane_mode_on {} && am broadcast -a android.intent.action.AIRPLANE_MODE --ez state {} >/dev/null)",
+            toggle(airplane),
+            if airplane { 1 } else { 0 },
+            airplane
+        ));
+    }
+    if let Some(wifi) = connectivity.wifi {
+        commands.push(format!("svc wifi {}", toggle(wifi)));
+    }
+    if let Some(data) = connectivity.data {
+        commands.push(format!("svc data {}", toggle(data)));
+    }
+    commands
+}
+
+pub fn parse_connectivity(output: &str) -> Connectivity {
+    let values: Vec<Option<bool>> = output.lines().map(|line| match line.trim() {
+        "null" | "" => None,
+        value => Some(value != "0"),
+    }).collect();
+
+    Connectivity {
+        wifi: values.first().copied().flatten(),
+        data: values.get(1).copied().flatten(),
+        airplane: values.get(2).copied().flatten(),
+    }
+}
+
+pub fn mock_location_commands(location: &MockLocation) -> Vec<String> {
+    let mut commands = vec!["appops set com.android.shell android:mock_location allow".to_string()];
+    let fix = format!("--location {},{} --accuracy {}", location.latitude, location.longitude, location.accuracy.unwrap_or(5.0));
+
+    for provider in MOCK_PROVIDERS {
+        // add-test-provider fails when the provider already exists, which is fine
+        commands.push(format!("(cmd location providers add-test-provider {} 2>/dev/null || true)", provider));
+        commands.push(format!("cmd location providers set-test-provider-enabled {} true", provider));
+        commands.push(format!("cmd location providers set-test-provider-location {} {}", provider, fix));
+    }
+    commands
+}
+
+pub fn reset_mock_location_commands() -> Vec<String> {
+    let mut commands: Vec<String> = MOCK_PROVIDERS.iter().map(|provider| format!("cmd location providers remove-test-provider {} 2>/dev/null", provider)).collect();
+    commands.push("appops set com.android.shell android:mock_location default".to_string());
+    commands
+}
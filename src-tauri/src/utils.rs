@@ -1,10 +1,12 @@
-use std::{fs::File, io::{BufRead, BufReader, Read}, path::Path, process::{Command, Stdio}};
+use std::{collections::BTreeMap, fs::File, io::{Cursor, Read, Seek}, path::Path, process::Command, sync::atomic::{AtomicU64, Ordering}, time::{SystemTime, UNIX_EPOCH}};
 
+use adb_client::{ADBDeviceExt, ADBServerDevice};
 use base64::{engine::general_purpose, Engine};
-use tauri::{AppHandle, Emitter};
 use which::{which, which_in};
 use zip::ZipArchive;
 
+use crate::{apksig::{self, SignatureSummary}, arsc::{ResourceConfig, ResourceTable}, axml::{self, XmlAttribute, XmlElement}, icon::{render_icon, to_png, ApkIconSource, DirIconSource}};
+
 #[derive(Debug, serde::Serialize, Default)]
 pub struct AppDetail {
     name: String,
@@ -15,6 +17,7 @@ pub struct AppDetail {
     is_32bit: bool,
     is_64bit: bool,
     icon_base64: Option<String>,
+    signature: Option<SignatureSummary>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -24,10 +27,6 @@ pub struct Directory {
     pub link_to: String,
 }
 
-pub fn get_aapt2() -> Option<String> {
-    which_in("aapt2", Some("binaries"), std::env::current_dir().unwrap()).ok().map(|path| path.to_string_lossy().to_string())
-}
-
 pub fn get_adb() -> Option<String> {
     which_in("adb", Some("binaries"), std::env::current_dir().unwrap()).ok().map(|path| path.to_string_lossy().to_string())
 }
@@ -39,6 +38,26 @@ pub fn get_scrcpy() -> Option<String> {
     }
 }
 
+/// The bundled scrcpy-server, build.rs copies `binaries/<os>` into `binaries` but keeps the macOS arch folders.
+pub fn get_scrcpy_server() -> Option<String> {
+    let platform = if cfg!(target_os = "windows") {
+        "windows"
+    } else if cfg!(target_os = "macos") {
+        if cfg!(target_arch = "x86_64") { "macos/x86_64" } else { "macos/arm" }
+    } else {
+        "linux"
+    };
+    let arch = platform.strip_prefix("macos/");
+    [
+        Some("binaries/scrcpy-server".to_string()),
+        arch.map(|arch| format!("binaries/{}/scrcpy-server", arch)),
+        Some(format!("binaries/{}/scrcpy-server", platform)),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|path| Path::new(path).exists())
+}
+
 pub fn get_app_detail_from_xapk(app_path: String) -> Option<AppDetail> {
     let file = File::open(&app_path).ok()?;
     let mut archive = ZipArchive::new(file).ok()?;
@@ -61,68 +80,130 @@ pub fn get_app_detail_from_xapk(app_path: String) -> Option<AppDetail> {
         if let Ok(mut icon_file) = archive.by_name(icon_path) {
             let mut icon_data = Vec::new();
             if icon_file.read_to_end(&mut icon_data).is_ok() {
-                app_detail.icon_base64 = Some(general_purpose::STANDARD.encode(&icon_data));
+                app_detail.icon_base64 = to_png(icon_data).map(|icon_data| general_purpose::STANDARD.encode(&icon_data));
             }
         }
     }
 
+    app_detail.signature = read_xapk_base(&mut archive)
+        .and_then(|base| apksig::verify(base.into_inner().get_ref()).ok())
+        .map(|report| report.summary());
+
     Some(app_detail)
 }
 
-
 pub fn get_app_detail_from_apk(app_path: String) -> Option<AppDetail> {
-    let path = Path::new(&app_path);
+    let file = File::open(&app_path).ok()?;
+    let mut archive = ZipArchive::new(file).ok()?;
+    let manifest = read_apk_manifest(&mut archive)?;
 
-    let aapt2_path = get_aapt2().expect("aapt2 not found");
-    let output = std::process::Command::new(aapt2_path)
-        .args(&["dump", "badging", &path.to_str().unwrap()])
-        .output()
-        .expect("failed to execute aapt2");
+    let mut app_detail = AppDetail {
+        package_name: manifest.attribute_value("package")?.to_string(),
+        version: manifest.attribute_value("versionName").unwrap_or_default().to_string(),
+        ..Default::default()
+    };
 
-    let output = String::from_utf8(output.stdout).expect("failed to convert output to string");
-    let mut app_detail = AppDetail::default();
-    let mut icon_path = String::new();
+    if let Some(uses_sdk) = manifest.child("uses-sdk") {
+        // Without minSdkVersion the platform defaults to 1, targetSdkVersion defaults to minSdkVersion
+        app_detail.min_sdk = uses_sdk.attribute_value("minSdkVersion").unwrap_or("1").to_string();
+        app_detail.target_sdk = uses_sdk.attribute_value("targetSdkVersion").unwrap_or(&app_detail.min_sdk).to_string();
+    }
 
-    for line in output.lines() {
-        if line.starts_with("package:") {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            app_detail.package_name = parts.get(1).unwrap().split("=").nth(1).unwrap().replace("'", "");
-            app_detail.version = parts.get(3).unwrap().split("=").nth(1).unwrap().replace("'", "");
-        } else if line.starts_with("sdkVersion:") {
-            app_detail.min_sdk = line.split(':').nth(1).unwrap().trim().replace("'", "");
-        } else if line.starts_with("targetSdkVersion:") {
-            app_detail.target_sdk = line.split(':').nth(1).unwrap().trim().replace("'", "");
-        } else if line.starts_with("application-label:") {
-            app_detail.name = line.split(':').nth(1).unwrap().trim().replace("'", "");
-        } else if line.starts_with("application:") {
-            if let Some(start) = line.find("icon='") {
-                let end = line[start + 6..].find('\'').unwrap_or(line.len());
-                icon_path = line[start + 6..start + 6 + end].to_string();
-            }
-        } else if line.starts_with("native-code:") {
-            let native_code = line.split(':').nth(1).unwrap().trim().replace("'", "");
-            let archs: Vec<&str> = native_code.split_whitespace().collect();
-            app_detail.is_32bit = archs.contains(&"armeabi-v7a");
-            app_detail.is_64bit = archs.contains(&"arm64-v8a");
-        }
+    let resources = read_apk_resources(&mut archive);
+    let application = manifest.child("application");
+
+    app_detail.name = application
+        .and_then(|application| application.attribute("label"))
+        .and_then(|label| match (label.reference(), &resources) {
+            (Some(id), Some(resources)) => resources.resolve_string(id, &ResourceConfig::default()),
+            (Some(_), None) => None,
+            (None, _) => Some(label.value.clone()),
+        })
+        .filter(|label| !label.is_empty())
+        .unwrap_or(app_detail.package_name.clone());
+
+    let icon_id = application
+        .and_then(|application| application.attribute("icon").or(application.attribute("roundIcon")))
+        .and_then(|icon| icon.reference());
+    if let (Some(icon_id), Some(resources)) = (icon_id, &resources) {
+        // Prefer the bitmap the app ships, adaptive and vector icons are rendered when there is none
+        let icon_data = match resources.best_bitmap(icon_id) {
+            Some(icon_path) => archive.by_name(&icon_path).ok().and_then(|mut icon_file| {
+                let mut icon_data = Vec::new();
+                icon_file.read_to_end(&mut icon_data).ok()?;
+                to_png(icon_data)
+            }),
+            None => render_icon(&mut ApkIconSource { archive: &mut archive, resources }, &format!("@0x{:08x}", icon_id)),
+        };
+        app_detail.icon_base64 = icon_data.map(|icon_data| general_purpose::STANDARD.encode(&icon_data));
     }
 
-    if !icon_path.is_empty() {
-        if let Ok(file) = File::open(&path) {
-            if let Ok(mut archive) = ZipArchive::new(file) {
-                if let Ok(mut icon_file) = archive.by_name(&icon_path) {
-                    let mut icon_data = Vec::new();
-                    if icon_file.read_to_end(&mut icon_data).is_ok() {
-                        app_detail.icon_base64 = Some(general_purpose::STANDARD.encode(&icon_data));
-                    }
-                }
-            }
+    for name in archive.file_names() {
+        if let Some(abi) = name.strip_prefix("lib/").and_then(|rest| rest.split('/').next()) {
+            app_detail.is_32bit |= abi == "armeabi-v7a";
+            app_detail.is_64bit |= abi == "arm64-v8a";
         }
     }
 
+    app_detail.signature = std::fs::read(&app_path).ok().and_then(|data| apksig::verify(&data).ok()).map(|report| report.summary());
+
     Some(app_detail)
 }
 
+/// Decodes the binary AndroidManifest.xml of an APK.
+pub fn read_apk_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<XmlElement> {
+    let mut data = Vec::new();
+    archive.by_name("AndroidManifest.xml").ok()?.read_to_end(&mut data).ok()?;
+    axml::parse(&data)
+}
+
+/// Opens the base APK inside an XAPK/APKS bundle.
+pub fn read_xapk_base<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<ZipArchive<Cursor<Vec<u8>>>> {
+    let package_name = archive.by_name("manifest.json").ok()
+        .and_then(|file| serde_json::from_reader::<_, serde_json::Value>(file).ok())
+        .and_then(|manifest| manifest["package_name"].as_str().map(|name| format!("{}.apk", name)));
+    let names: Vec<String> = archive.file_names().map(|name| name.to_string()).collect();
+    let base = package_name.filter(|name| names.contains(name))
+        .or(names.iter().find(|name| *name == "base.apk").cloned())
+        .or(names.iter().find(|name| name.ends_with(".apk") && !name.starts_with("config.") && !name.starts_with("split_")).cloned())?;
+
+    let mut data = Vec::new();
+    archive.by_name(&base).ok()?.read_to_end(&mut data).ok()?;
+    ZipArchive::new(Cursor::new(data)).ok()
+}
+
+/// Reads the manifest of a decompiled app, with the uses-sdk apktool moved to apktool.yml put back.
+pub fn read_dir_manifest(app_path: &Path) -> Option<XmlElement> {
+    let mut manifest = axml::parse_text(&std::fs::read_to_string(app_path.join("AndroidManifest.xml")).ok()?)?;
+    if manifest.child("uses-sdk").is_none() {
+        let apktool_data: serde_yaml::Value = std::fs::read_to_string(app_path.join("apktool.yml")).ok()
+            .and_then(|data| serde_yaml::from_str(&data).ok())
+            .unwrap_or_default();
+        let attributes = ["minSdkVersion", "targetSdkVersion"].iter()
+            .filter_map(|name| {
+                let value = &apktool_data["sdkInfo"][*name];
+                let value = value.as_i64().map(|v| v.to_string()).or(value.as_str().map(|v| v.to_string()))?;
+                Some(XmlAttribute {
+                    namespace: Some(axml::ANDROID_NS.to_string()),
+                    name: name.to_string(),
+                    resource_id: None,
+                    data_type: axml::TYPE_STRING,
+                    data: 0,
+                    value,
+                })
+            })
+            .collect();
+        manifest.children.insert(0, XmlElement { name: "uses-sdk".to_string(), attributes, ..Default::default() });
+    }
+    Some(manifest)
+}
+
+pub fn read_apk_resources<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<ResourceTable> {
+    let mut data = Vec::new();
+    archive.by_name("resources.arsc").ok()?.read_to_end(&mut data).ok()?;
+    ResourceTable::parse(&data)
+}
+
 pub fn get_app_detail_from_dir(app_path: String) -> Option<AppDetail> {
     let manifest = Path::new(&app_path).join("AndroidManifest.xml");
     let apktool_yml = Path::new(&app_path).join("apktool.yml");
@@ -144,35 +225,18 @@ pub fn get_app_detail_from_dir(app_path: String) -> Option<AppDetail> {
     let doc = roxmltree::Document::parse(&manifest_content).ok()?;
 
     app_detail.package_name = doc.descendants().find(|n| n.has_tag_name("manifest"))?.attribute("package")?.to_string();
-    app_detail.name = doc.descendants().find(|n| n.has_tag_name("application"))?.attributes().find(|attr| attr.name().ends_with("label"))?.value().to_string();
-
-    if app_detail.name.starts_with("@string/") {
-        let string_name = app_detail.name.trim_start_matches("@string/");
-        let strings_path = Path::new(&app_path).join("res/values/strings.xml");
-        if strings_path.exists() {
-            let strings_content = std::fs::read_to_string(&strings_path).ok()?;
-            let strings_doc = roxmltree::Document::parse(&strings_content).ok()?;
-            app_detail.name = strings_doc.descendants().find(|n| n.has_tag_name("string") && n.attribute("name") == Some(string_name))?.text().unwrap().to_string();
-        }
-    }
-    
-    if let Some(icon_reference) = doc.descendants().find(|n| n.has_tag_name("application"))?.attributes().find(|attr| attr.name().ends_with("icon")){
-        let icon_name = icon_reference.value().trim_start_matches("@mipmap/").trim_start_matches("@drawable/");
-        
-        if let Ok(entries) = std::fs::read_dir(&res_path) {
-            for entry in entries.flatten() {
-                let folder_name = entry.file_name().to_string_lossy().to_string();
-                if folder_name.starts_with("mipmap") || folder_name.starts_with("drawable") {
-                    let icon_path = entry.path().join(format!("{}.png", icon_name));
-                    if icon_path.exists() {
-                        if let Ok(icon_data) = std::fs::read(&icon_path) {
-                            app_detail.icon_base64 = Some(general_purpose::STANDARD.encode(&icon_data));
-                            break;
-                        }
-                    }
-                }
-            }
-        }
+    let application = doc.descendants().find(|n| n.has_tag_name("application"))?;
+    let label = application.attributes().find(|attr| attr.name() == "label").map(|attr| attr.value()).unwrap_or_default();
+    app_detail.name = resolve_dir_value(&res_path, label)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(app_detail.package_name.clone());
+
+    if let Some(icon_reference) = application.attributes().find(|attr| attr.name() == "icon") {
+        let icon_data = match find_dir_bitmap(&res_path, icon_reference.value()) {
+            Some(icon_path) => std::fs::read(&icon_path).ok().and_then(to_png),
+            None => render_icon(&mut DirIconSource { res_path: &res_path }, icon_reference.value()),
+        };
+        app_detail.icon_base64 = icon_data.map(|icon_data| general_purpose::STANDARD.encode(&icon_data));
     }
     
     let lib_path = Path::new(&app_path).join("lib");
@@ -188,56 +252,109 @@ pub fn get_app_detail_from_dir(app_path: String) -> Option<AppDetail> {
     Some(app_detail)
 }
 
-pub fn run_java_tool(
-    handle: AppHandle,
-    tool_name: &str,
-    args: &[&str],
-    success_msg: String,
-    error_msg: String,
-) {
-    let tool_path = format!("binaries/{}.jar", tool_name);
-    let tool_path = Path::new(&tool_path);
-    
-    if !tool_path.exists() {
-        handle.emit("log-error", format!("{}.jar not found", tool_name)).unwrap();
-        return;
-    }
-
-    let mut cmd = Command::new("java")
-        .args(&["-jar", tool_path.to_str().unwrap()])
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect(&format!("Failed to start {}", tool_name));
-
-    let stdout = cmd.stdout.take().expect("Failed to get stdout");
-    let stderr = cmd.stderr.take().expect("Failed to get stderr");
-
-    let handle_clone = handle.clone();
-    std::thread::spawn(move || {
-        let reader = BufReader::new(stdout);
-        for line in reader.lines().flatten() {
-            handle_clone.emit("log", line.trim()).unwrap();
+/// `res/values*` folders of a decompiled app, the unqualified `values` first.
+fn values_dirs(res_path: &Path) -> Vec<std::path::PathBuf> {
+    let mut dirs: Vec<_> = std::fs::read_dir(res_path)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    dirs.retain(|dir| dir.file_name().and_then(|name| name.to_str()).is_some_and(|name| name == "values" || name.starts_with("values-")));
+    dirs.sort_by_key(|dir| (dir.file_name().map(|name| name != "values"), dir.clone()));
+    dirs
+}
+
+/// Looks up the value of a `@type/name` reference in the values of a decompiled app, without following aliases.
+pub fn lookup_dir_value(res_path: &Path, reference: &str) -> Option<String> {
+    let (res_type, name) = reference.strip_prefix('@')?.split_once('/')?;
+    if res_type.contains(':') {
+        return None; // Framework resources aren't part of the app
+    }
+
+    values_dirs(res_path).iter()
+        .flat_map(|dir| std::fs::read_dir(dir).into_iter().flatten().flatten())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "xml"))
+        .find_map(|entry| {
+            let content = std::fs::read_to_string(entry.path()).ok()?;
+            let doc = roxmltree::Document::parse(&content).ok()?;
+            let node = doc.root_element().children().find(|n| {
+                n.attribute("name") == Some(name) && (n.has_tag_name(res_type) || (n.has_tag_name("item") && n.attribute("type") == Some(res_type)))
+            })?;
+            let text: String = node.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect();
+            Some(unescape_resource_string(text.trim()))
+        })
+}
+
+/// Resolves `@type/name` references against the values of a decompiled app, plain values are returned as is.
+pub fn resolve_dir_value(res_path: &Path, value: &str) -> Option<String> {
+    let mut value = value.to_string();
+    // Aliases can point to other resources, the limit guards against cycles
+    for _ in 0..16 {
+        if !value.starts_with('@') {
+            return Some(value);
         }
-    });
+        value = lookup_dir_value(res_path, &value)?;
+    }
+    None
+}
 
-    let handle_clone = handle.clone();
-    std::thread::spawn(move || {
-        let reader = BufReader::new(stderr);
-        for line in reader.lines().flatten() {
-            handle_clone.emit("log", line.trim()).unwrap();
+/// Undoes the escaping aapt applies to string resources.
+fn unescape_resource_string(value: &str) -> String {
+    let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
         }
-    });
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(escaped) => result.push(escaped),
+            None => {}
+        }
+    }
+    result
+}
+
+/// Density of a res folder from its qualifiers, folders without one count as mdpi.
+pub fn dir_density(folder_name: &str) -> u16 {
+    folder_name.split('-').skip(1).find_map(|qualifier| match qualifier {
+        "ldpi" => Some(120),
+        "mdpi" | "nodpi" => Some(160),
+        "tvdpi" => Some(213),
+        "hdpi" => Some(240),
+        "xhdpi" => Some(320),
+        "xxhdpi" => Some(480),
+        "xxxhdpi" => Some(640),
+        _ => qualifier.strip_suffix("dpi")?.parse().ok(),
+    }).unwrap_or(160)
+}
 
-    std::thread::spawn(move || {
-        let status = cmd.wait().expect("Failed to wait on child process");
-        if status.success() {
-            handle.emit("log", success_msg).unwrap();
-        } else {
-            handle.emit("log", error_msg).unwrap();
+/// Finds the highest density bitmap for a `@mipmap/` or `@drawable/` reference in a decompiled app.
+fn find_dir_bitmap(res_path: &Path, reference: &str) -> Option<std::path::PathBuf> {
+    let mut reference = reference.to_string();
+    for _ in 0..16 {
+        let (res_type, name) = reference.strip_prefix('@')?.split_once('/')?;
+        let bitmap = std::fs::read_dir(res_path).ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let folder_name = entry.file_name().to_string_lossy().to_string();
+                if folder_name != res_type && !folder_name.starts_with(&format!("{}-", res_type)) {
+                    return None;
+                }
+                let path = ["png", "webp", "jpg"].iter()
+                    .map(|ext| entry.path().join(format!("{}.{}", name, ext)))
+                    .find(|path| path.exists())?;
+                Some((dir_density(&folder_name), path))
+            })
+            .max_by_key(|(density, _)| *density);
+        if let Some((_, path)) = bitmap {
+            return Some(path);
         }
-    });
+        // The drawable may be an alias declared in values
+        reference = lookup_dir_value(res_path, &reference)?;
+    }
+    None
 }
 
 pub fn parse_ls_output(output: &str) -> Vec<Directory> {
@@ -267,4 +384,635 @@ pub fn parse_ls_output(output: &str) -> Vec<Directory> {
     }
 
     directories
-}
\ No newline at end of file
+}
+#[derive(Debug, serde::Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentTarget {
+    #[default]
+    Activity,
+    Broadcast,
+    Service,
+    ForegroundService,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum IntentExtraValue {
+    String(String),
+    Int(i32),
+    Bool(bool),
+    Long(i64),
+    Float(f32),
+    Uri(String),
+    StringArray(Vec<String>),
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct IntentExtra {
+    pub key: String,
+    pub value: IntentExtraValue,
+}
+
+#[derive(Debug, serde::Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Intent {
+    pub target: IntentTarget,
+    pub action: Option<String>,
+    pub data: Option<String>,
+    pub mime_type: Option<String>,
+    pub categories: Vec<String>,
+    pub component: Option<String>,
+    pub package: Option<String>,
+    pub flags: Option<u32>,
+    pub extras: Vec<IntentExtra>,
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct IntentResult {
+    pub status: Option<String>,
+    pub launch_state: Option<String>,
+    pub activity: Option<String>,
+    pub total_time: Option<u64>,
+    pub wait_time: Option<u64>,
+    pub broadcast_result: Option<i32>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    pub output: String,
+}
+
+/// Quotes an argument for the device shell, adb joins arguments with spaces before handing them to `sh`.
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Wraps a command so it runs as root, `None` when the device isn't rooted. `shell` runs a command on the device.
+pub fn as_root(command: &str, shell: impl Fn(&str) -> Result<String, String>) -> Result<Option<String>, String> {
+    if shell("id -u")?.trim() == "0" {
+        return Ok(Some(command.to_string()));
+    }
+    if shell(&format!("su -c {} 2>/dev/null", shell_quote("id -u")))?.trim() == "0" {
+        return Ok(Some(format!("su -c {}", shell_quote(command))));
+    }
+    Ok(None)
+}
+
+/// Renders an intent into `am start` / `am broadcast` / `am startservice` arguments.
+pub fn build_intent_args(intent: &Intent) -> Vec<String> {
+    let mut args = vec!["am".to_string()];
+    match intent.target {
+        IntentTarget::Activity => args.extend(["start".to_string(), "-W".to_string()]),
+        IntentTarget::Broadcast => args.push("broadcast".to_string()),
+        IntentTarget::Service => args.push("startservice".to_string()),
+        IntentTarget::ForegroundService => args.push("start-foreground-service".to_string()),
+    }
+
+    if let Some(action) = &intent.action {
+        args.extend(["-a".to_string(), shell_quote(action)]);
+    }
+    if let Some(data) = &intent.data {
+        args.extend(["-d".to_string(), shell_quote(data)]);
+    }
+    if let Some(mime_type) = &intent.mime_type {
+        args.extend(["-t".to_string(), shell_quote(mime_type)]);
+    }
+    for category in &intent.categories {
+        args.extend(["-c".to_string(), shell_quote(category)]);
+    }
+    if let Some(flags) = intent.flags {
+        args.extend(["-f".to_string(), flags.to_string()]);
+    }
+
+    for extra in &intent.extras {
+        let (flag, value) = match &extra.value {
+            IntentExtraValue::String(value) => ("--es", value.clone()),
+            IntentExtraValue::Int(value) => ("--ei", value.to_string()),
+            IntentExtraValue::Bool(value) => ("--ez", value.to_string()),
+            IntentExtraValue::Long(value) => ("--el", value.to_string()),
+            IntentExtraValue::Float(value) => ("--ef", value.to_string()),
+            IntentExtraValue::Uri(value) => ("--eu", value.clone()),
+            // am splits string arrays on unescaped commas
+            IntentExtraValue::StringArray(values) => ("--esa", values.iter().map(|v| v.replace(',', "\\,")).collect::<Vec<_>>().join(",")),
+        };
+        args.extend([flag.to_string(), shell_quote(&extra.key), shell_quote(&value)]);
+    }
+
+    if let Some(component) = &intent.component {
+        args.extend(["-n".to_string(), shell_quote(component)]);
+    } else if let Some(package) = &intent.package {
+        args.extend(["-p".to_string(), shell_quote(package)]);
+    }
+
+    args
+}
+
+pub fn parse_intent_output(output: &str) -> IntentResult {
+    let mut result = IntentResult {
+        output: output.to_string(),
+        ..Default::default()
+    };
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some((key, value)) = line.split_once(':') {
+            let value = value.trim();
+            match key {
+                "Status" => result.status = Some(value.to_string()),
+                "LaunchState" => result.launch_state = Some(value.to_string()),
+                "Activity" => result.activity = Some(value.to_string()),
+                "TotalTime" => result.total_time = value.parse().ok(),
+                "WaitTime" => result.wait_time = value.parse().ok(),
+                "Broadcast completed" => {
+                    result.broadcast_result = value.trim_start_matches("result=").split(',').next().and_then(|code| code.trim().parse().ok());
+                    result.status.get_or_insert("ok".to_string());
+                }
+                "Warning" => result.warnings.push(value.to_string()),
+                "Error" | "Exception occurred while executing" => result.errors.push(value.to_string()),
+                _ if key.ends_with("Exception") => result.errors.push(line.to_string()),
+                _ => {}
+            }
+        } else if line.starts_with("Error type") {
+            result.errors.push(line.to_string());
+        }
+    }
+
+    if result.status.is_none() && result.errors.is_empty() && output.starts_with("Starting service:") {
+        result.status = Some("ok".to_string());
+    } else if result.status.is_none() && !result.errors.is_empty() {
+        result.status = Some("error".to_string());
+    }
+
+    result
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct PermissionState {
+    pub name: String,
+    pub granted: bool,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct PackagePermissions {
+    pub requested: Vec<String>,
+    pub install: Vec<PermissionState>,
+    pub runtime: Vec<PermissionState>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AppOp {
+    pub name: String,
+    pub mode: String,
+    pub uid_mode: bool,
+    pub detail: Option<String>,
+}
+
+/// Turns the output of `pm`/`appops` into an error when the tool reports a failure, both print nothing on success.
+pub fn check_shell_output(output: String) -> Result<String, String> {
+    let failed = output.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with("Error") || line.starts_with("Failure") || line.starts_with("Exception") || line.contains("Exception:")
+    });
+    if failed {
+        Err(output.trim().to_string())
+    } else {
+        Ok(output.trim().to_string())
+    }
+}
+
+fn parse_permission_state(line: &str) -> PermissionState {
+    let (name, rest) = line.split_once(':').unwrap_or((line, ""));
+    let flags = rest.find("flags=[").map(|start| {
+        rest[start + 7..].trim_end().trim_end_matches(']').split('|').map(|flag| flag.trim().to_string()).filter(|flag| !flag.is_empty()).collect()
+    });
+
+    PermissionState {
+        name: name.trim().to_string(),
+        granted: rest.contains("granted=true"),
+        flags: flags.unwrap_or_default(),
+    }
+}
+
+pub fn parse_package_permissions(output: &str, user_id: u32) -> PackagePermissions {
+    let mut permissions = PackagePermissions::default();
+    let mut section = "";
+    let mut section_indent = 0;
+    let mut current_user = None;
+
+    for line in output.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(user) = trimmed.strip_prefix("User ").and_then(|rest| rest.split(':').next()) {
+            current_user = user.parse::<u32>().ok();
+        }
+
+        if trimmed.ends_with("permissions:") {
+            section = match trimmed {
+                "requested permissions:" => "requested",
+                "install permissions:" => "install",
+                "runtime permissions:" => "runtime",
+                _ => "",
+            };
+            section_indent = indent;
+            continue;
+        }
+        if indent <= section_indent {
+            section = "";
+            continue;
+        }
+
+        match section {
+            "requested" => {
+                let name = trimmed.split([':', ',']).next().unwrap_or(trimmed).to_string();
+                if !permissions.requested.contains(&name) {
+                    permissions.requested.push(name);
+                }
+            }
+            "install" => permissions.install.push(parse_permission_state(trimmed)),
+            // Every user section lists its own runtime permissions
+            "runtime" if current_user.unwrap_or(0) == user_id => {
+                let state = parse_permission_state(trimmed);
+                if !permissions.runtime.iter().any(|p| p.name == state.name) {
+                    permissions.runtime.push(state);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    permissions
+}
+
+pub fn parse_appops_output(output: &str) -> Vec<AppOp> {
+    let mut ops = Vec::new();
+
+    for line in output.lines() {
+        let mut line = line.trim();
+        let uid_mode = line.starts_with("Uid mode:");
+        if uid_mode {
+            line = line.trim_start_matches("Uid mode:").trim();
+        }
+
+        let Some((name, rest)) = line.split_once(':') else { continue };
+        if name.contains(' ') {
+            continue;
+        }
+        let (mode, detail) = match rest.split_once(';') {
+            Some((mode, detail)) => (mode.trim(), Some(detail.trim().to_string())),
+            None => (rest.trim(), None),
+        };
+
+        ops.push(AppOp {
+            name: name.to_string(),
+            mode: mode.to_string(),
+            uid_mode,
+            detail,
+        });
+    }
+
+    ops
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsNamespace {
+    Global,
+    System,
+    Secure,
+}
+
+impl SettingsNamespace {
+    pub const ALL: [SettingsNamespace; 3] = [SettingsNamespace::Global, SettingsNamespace::System, SettingsNamespace::Secure];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SettingsNamespace::Global => "global",
+            SettingsNamespace::System => "system",
+            SettingsNamespace::Secure => "secure",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default)]
+pub struct SettingsSnapshot {
+    pub device_id: String,
+    pub created_at: u64,
+    pub global: BTreeMap<String, String>,
+    pub system: BTreeMap<String, String>,
+    pub secure: BTreeMap<String, String>,
+}
+
+impl SettingsSnapshot {
+    pub fn namespace(&self, namespace: SettingsNamespace) -> &BTreeMap<String, String> {
+        match namespace {
+            SettingsNamespace::Global => &self.global,
+            SettingsNamespace::System => &self.system,
+            SettingsNamespace::Secure => &self.secure,
+        }
+    }
+
+    pub fn namespace_mut(&mut self, namespace: SettingsNamespace) -> &mut BTreeMap<String, String> {
+        match namespace {
+            SettingsNamespace::Global => &mut self.global,
+            SettingsNamespace::System => &mut self.system,
+            SettingsNamespace::Secure => &mut self.secure,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct RestoreReport {
+    pub applied: usize,
+    pub unchanged: usize,
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum QuickSetting {
+    AnimationScale(f32),
+    StayAwake(bool),
+    ShowTaps(bool),
+    PointerLocation(bool),
+    DarkMode(bool),
+    FontScale(f32),
+    Locale(String),
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct QuickSettings {
+    pub window_animation_scale: Option<f32>,
+    pub transition_animation_scale: Option<f32>,
+    pub animator_duration_scale: Option<f32>,
+    pub stay_awake: bool,
+    pub show_taps: bool,
+    pub pointer_location: bool,
+    pub dark_mode: bool,
+    pub font_scale: Option<f32>,
+    pub locale: Option<String>,
+}
+
+/// Shell commands that apply a curated setting, values are already quoted for the device shell.
+pub fn quick_setting_commands(setting: &QuickSetting) -> Vec<String> {
+    let flag = |enabled: &bool| if *enabled { "1" } else { "0" };
+    match setting {
+        QuickSetting::AnimationScale(scale) => ["window_animation_scale", "transition_animation_scale", "animator_duration_scale"]
+            .iter()
+            .map(|key| format!("settings put global {} {}", key, scale))
+            .collect(),
+        // 7 = stay on while plugged into AC, USB and wireless chargers
+        QuickSetting::StayAwake(enabled) => vec![format!("settings put global stay_on_while_plugged_in {}", if *enabled { "7" } else { "0" })],
+        QuickSetting::ShowTaps(enabled) => vec![format!("settings put system show_touches {}", flag(enabled))],
+        QuickSetting::PointerLocation(enabled) => vec![format!("settings put system pointer_location {}", flag(enabled))],
+        QuickSetting::DarkMode(enabled) => vec![format!("cmd uimode night {}", if *enabled { "yes" } else { "no" })],
+        QuickSetting::FontScale(scale) => vec![format!("settings put system font_scale {}", scale)],
+        QuickSetting::Locale(locale) => vec![format!("settings put system system_locales {}", shell_quote(locale))],
+    }
+}
+
+pub fn parse_settings_list(output: &str) -> Vec<Setting> {
+    output.lines().filter_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        Some(Setting {
+            key: key.trim().to_string(),
+            value: value.to_string(),
+        })
+    }).collect()
+}
+
+/// `settings get` prints the literal `null` for missing keys.
+pub fn parse_setting_value(output: &str) -> Option<String> {
+    let value = output.trim_end_matches(['\r', '\n']);
+    if value == "null" {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub user: String,
+    pub name: String,
+    pub rss: u64, // KiB
+    pub cpu: f32,
+    pub state: String,
+    pub package: Option<String>,
+}
+
+/// Parses `pm list packages -U` into a uid (without the user offset) to package map.
+pub fn parse_package_uids(output: &str) -> BTreeMap<u32, Vec<String>> {
+    let mut uids: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for line in output.lines() {
+        let Some(rest) = line.trim().strip_prefix("package:") else { continue };
+        let Some((package, uid)) = rest.split_once(" uid:") else { continue };
+        // Packages shared between users are printed as uid:10123,1010123
+        for uid in uid.split(',').filter_map(|uid| uid.trim().parse::<u32>().ok()) {
+            let packages = uids.entry(uid % 100000).or_default();
+            if !packages.iter().any(|p| p == package) {
+                packages.push(package.to_string());
+            }
+        }
+    }
+    uids
+}
+
+/// Converts an Android user name such as `u0_a123` into its app uid (10123).
+fn app_uid_from_user(user: &str) -> Option<u32> {
+    let (_, app) = user.strip_prefix('u')?.split_once("_a")?;
+    Some(10000 + app.parse::<u32>().ok()?)
+}
+
+/// Parses `ps -A -o PID,PPID,USER,RSS,%CPU,S,NAME` output.
+pub fn parse_ps_output(output: &str, packages: &BTreeMap<u32, Vec<String>>) -> Vec<ProcessInfo> {
+    output.lines().skip(1).filter_map(|line| {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 7 {
+            return None;
+        }
+        let name = parts[6..].join(" ");
+        let process_package = name.split(':').next().unwrap_or(&name);
+        let package = app_uid_from_user(parts[2]).and_then(|uid| packages.get(&uid)).and_then(|candidates| {
+            candidates.iter().find(|p| p.as_str() == process_package).or(candidates.first()).cloned()
+        });
+
+        Some(ProcessInfo {
+            pid: parts[0].parse().ok()?,
+            ppid: parts[1].parse().unwrap_or(0),
+            user: parts[2].to_string(),
+            rss: parts[3].parse().unwrap_or(0),
+            cpu: parts[4].parse().unwrap_or(0.0),
+            state: parts[5].to_string(),
+            name,
+            package,
+        })
+    }).collect()
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct UiNode {
+    pub index: u32,
+    pub class: String,
+    pub package: String,
+    pub resource_id: String,
+    pub text: String,
+    pub content_desc: String,
+    pub bounds: [i32; 4], // left, top, right, bottom
+    pub center: [i32; 2],
+    pub clickable: bool,
+    pub long_clickable: bool,
+    pub scrollable: bool,
+    pub checkable: bool,
+    pub checked: bool,
+    pub enabled: bool,
+    pub focused: bool,
+    pub selected: bool,
+    pub children: Vec<UiNode>,
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct UiHierarchy {
+    pub rotation: u32,
+    pub nodes: Vec<UiNode>,
+    pub screenshot_base64: Option<String>,
+}
+
+pub fn adb_command(device_id: &str) -> Command {
+    let adb = get_adb().or_else(|| which("adb").ok().map(|path| path.to_string_lossy().to_string())).expect("adb not found");
+    let mut command = Command::new(adb);
+    command.args(["-s", device_id]);
+    command
+}
+
+/// Parses uiautomator bounds such as `[0,63][1080,210]`.
+fn parse_bounds(bounds: &str) -> [i32; 4] {
+    let values: Vec<i32> = bounds.split(['[', ']', ',']).filter_map(|v| v.parse().ok()).collect();
+    match values.as_slice() {
+        [left, top, right, bottom] => [*left, *top, *right, *bottom],
+        _ => [0; 4],
+    }
+}
+
+fn parse_ui_node(node: roxmltree::Node) -> UiNode {
+    let attribute = |name: &str| node.attribute(name).unwrap_or_default().to_string();
+    let flag = |name: &str| node.attribute(name) == Some("true");
+    let bounds = parse_bounds(node.attribute("bounds").unwrap_or_default());
+
+    UiNode {
+        index: node.attribute("index").and_then(|v| v.parse().ok()).unwrap_or(0),
+        class: attribute("class"),
+        package: attribute("package"),
+        resource_id: attribute("resource-id"),
+        text: attribute("text"),
+        content_desc: attribute("content-desc"),
+        bounds,
+        center: [(bounds[0] + bounds[2]) / 2, (bounds[1] + bounds[3]) / 2],
+        clickable: flag("clickable"),
+        long_clickable: flag("long-clickable"),
+        scrollable: flag("scrollable"),
+        checkable: flag("checkable"),
+        checked: flag("checked"),
+        enabled: flag("enabled"),
+        focused: flag("focused"),
+        selected: flag("selected"),
+        children: node.children().filter(|n| n.has_tag_name("node")).map(parse_ui_node).collect(),
+    }
+}
+
+pub fn parse_ui_hierarchy(xml: &str) -> Option<UiHierarchy> {
+    // uiautomator may print warnings before the document itself
+    let xml = &xml[xml.find("<?xml").or(xml.find("<hierarchy"))?..];
+    let xml = &xml[..xml.rfind("</hierarchy>")? + "</hierarchy>".len()];
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    let root = doc.descendants().find(|n| n.has_tag_name("hierarchy"))?;
+
+    Some(UiHierarchy {
+        rotation: root.attribute("rotation").and_then(|v| v.parse().ok()).unwrap_or(0),
+        nodes: root.children().filter(|n| n.has_tag_name("node")).map(parse_ui_node).collect(),
+        screenshot_base64: None,
+    })
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UserInfo {
+    pub id: u32,
+    pub name: String,
+    pub flags: u32,
+    pub running: bool,
+    pub managed_profile: bool,
+    pub guest: bool,
+}
+
+/// Parses `pm list users`, lines look like `UserInfo{10:Work profile:1030} running`.
+pub fn parse_users(output: &str) -> Vec<UserInfo> {
+    output.lines().filter_map(|line| {
+        let line = line.trim();
+        let start = line.find("UserInfo{")? + "UserInfo{".len();
+        let end = line.rfind('}')?;
+        let info = &line[start..end];
+        let (id, rest) = info.split_once(':')?;
+        let (name, flags) = rest.rsplit_once(':')?;
+        let flags = u32::from_str_radix(flags, 16).unwrap_or(0);
+
+        Some(UserInfo {
+            id: id.parse().ok()?,
+            name: name.to_string(),
+            flags,
+            running: line[end..].contains("running"),
+            // UserInfo.FLAG_MANAGED_PROFILE and FLAG_GUEST
+            managed_profile: flags & 0x20 != 0,
+            guest: flags & 0x4 != 0,
+        })
+    }).collect()
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PackageEntry {
+    pub package: String,
+    pub path: Option<String>,
+}
+
+/// Parses `pm list packages -f`, lines look like `package:/data/app/.../base.apk=com.example`.
+pub fn parse_package_list(output: &str) -> Vec<PackageEntry> {
+    output.lines().filter_map(|line| {
+        let rest = line.trim().strip_prefix("package:")?;
+        Some(match rest.rsplit_once('=') {
+            Some((path, package)) => PackageEntry { package: package.to_string(), path: Some(path.to_string()) },
+            None => PackageEntry { package: rest.to_string(), path: None },
+        })
+    }).collect()
+}
+
+/// `--user <id>` for pm/appops/run-as, empty when targeting the default user.
+pub fn user_args(user_id: Option<u32>) -> Vec<String> {
+    match user_id {
+        Some(user_id) => vec!["--user".to_string(), user_id.to_string()],
+        None => Vec::new(),
+    }
+}
+
+static INSTALL_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// `pm install` only reads from the device, so the APK is staged in /data/local/tmp under a name unique to this install.
+pub fn install_staged_apk(device: &mut ADBServerDevice, apk_path: &str, user_id: Option<u32>) -> Result<String, String> {
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or_default();
+    let remote_path = format!("/data/local/tmp/tuyu_install_{}_{}.apk", stamp, INSTALL_COUNT.fetch_add(1, Ordering::Relaxed));
+    let mut file = File::open(apk_path).map_err(|e| e.to_string())?;
+    device.push(&mut file, &remote_path).map_err(|e| e.to_string())?;
+
+    let mut output = Vec::new();
+    let command = format!("pm install -r {} {} 2>&1; rm -f {}", user_args(user_id).join(" "), remote_path, remote_path);
+    device.shell_command(&[&command], &mut output).map_err(|e| e.to_string())?;
+    let output = String::from_utf8_lossy(&output).trim().to_string();
+    if output.ends_with("Success") { Ok(output) } else { Err(output) }
+}
@@ -0,0 +1,100 @@
+//! In-process replacement for the Android SDK `zipalign`.
+
+use std::{fs::File, io::{BufReader, BufWriter}, path::Path};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Uncompressed entries are aligned to 4 bytes so they can be mmapped by the platform.
+pub const ALIGNMENT: u64 = 4;
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct MisalignedEntry {
+    pub name: String,
+    pub offset: u64,
+    pub alignment: u64,
+}
+
+#[derive(Debug, serde::Serialize, Clone)]
+pub struct AlignmentReport {
+    pub path: String,
+    pub aligned: bool,
+    pub checked: usize,
+    pub misaligned: Vec<MisalignedEntry>,
+}
+
+/// Like `zipalign -P <page_size>`, native libraries are page aligned (in KiB) so they can be loaded from the APK.
+fn required_alignment(name: &str, page_size: Option<u32>) -> u64 {
+    match page_size {
+        Some(page_size) if name.ends_with(".so") => page_size as u64 * 1024,
+        _ => ALIGNMENT,
+    }
+}
+
+/// Rewrites `input` with aligned uncompressed entries, compressed entries are copied as is.
+/// Any APK signature is dropped, so align before signing.
+pub fn align(input: &Path, output: &Path, page_size: Option<u32>) -> Result<(), String> {
+    if page_size.is_some_and(|page_size| page_size != 4 && page_size != 16) {
+        return Err("Page size must be 4 or 16 KiB".to_string());
+    }
+    let mut archive = ZipArchive::new(BufReader::new(File::open(input).map_err(|e| e.to_string())?)).map_err(|e| e.to_string())?;
+    // Aligning in place goes through a temporary file next to the output
+    let temp = output.with_extension("aligning");
+    let mut writer = ZipWriter::new(BufWriter::new(File::create(&temp).map_err(|e| e.to_string())?));
+
+    let result = (|| {
+        for index in 0..archive.len() {
+            let mut file = archive.by_index_raw(index).map_err(|e| e.to_string())?;
+            if file.is_dir() || file.compression() != CompressionMethod::Stored {
+                writer.raw_copy_file(file).map_err(|e| e.to_string())?;
+                continue;
+            }
+
+            let alignment = required_alignment(file.name(), page_size);
+            let mut options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Stored)
+                .with_alignment(alignment as u16)
+                .large_file(file.size() >= u32::MAX as u64);
+            if let Some(modified) = file.last_modified() {
+                options = options.last_modified_time(modified);
+            }
+            if let Some(mode) = file.unix_mode() {
+                options = options.unix_permissions(mode);
+            }
+            let name = file.name().to_string();
+            writer.start_file(name, options).map_err(|e| e.to_string())?;
+            std::io::copy(&mut file, &mut writer).map_err(|e| e.to_string())?;
+        }
+        writer.finish().map_err(|e| e.to_string())?;
+        Ok(())
+    })();
+
+    // Windows can't replace a file that is still open, and output is the input when aligning in place
+    drop(archive);
+    match result {
+        Ok(()) => std::fs::rename(&temp, output).map_err(|e| e.to_string()),
+        Err(error) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(error)
+        }
+    }
+}
+
+/// Checks the alignment of every uncompressed entry, like `zipalign -c`.
+pub fn verify(path: &Path, page_size: Option<u32>) -> Result<AlignmentReport, String> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path).map_err(|e| e.to_string())?)).map_err(|e| e.to_string())?;
+    let mut report = AlignmentReport { path: path.to_string_lossy().to_string(), aligned: true, checked: 0, misaligned: Vec::new() };
+
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index).map_err(|e| e.to_string())?;
+        if file.is_dir() || file.compression() != CompressionMethod::Stored {
+            continue;
+        }
+        report.checked += 1;
+        let alignment = required_alignment(file.name(), page_size);
+        if file.data_start() % alignment != 0 {
+            report.misaligned.push(MisalignedEntry { name: file.name().to_string(), offset: file.data_start(), alignment });
+        }
+    }
+    report.aligned = report.misaligned.is_empty();
+    Ok(report)
+}